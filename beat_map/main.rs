@@ -0,0 +1,176 @@
+// 离线分析关卡音乐, 生成游戏读取的节拍文件
+// 用法: cargo run --bin beat_map -- assets/music/xxx.ogg [输出路径]
+// 不指定输出路径时写到音乐旁边的 xxx.beats.toml
+use rodio::{Decoder, Source};
+use rustfft::{FftPlanner, num_complex::Complex};
+use std::{
+    env,
+    fs::{File, write},
+    io::BufReader,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+// 频谱分析的帧长和帧移(采样数)
+const FRAME_SIZE: usize = 1024;
+const HOP_SIZE: usize = 512;
+// 音量包络的采样间隔(秒)
+const ENVELOPE_INTERVAL: f64 = 0.05;
+// 自适应阈值取前后多少秒的平均频谱通量
+const THRESHOLD_WINDOW: f64 = 0.3;
+// 超过平均值多少倍才算节拍
+const THRESHOLD_SCALE: f32 = 1.5;
+// 相邻两拍的最小间隔(秒)
+const MIN_BEAT_GAP: f64 = 0.1;
+// 最弱的节拍也保留的强度
+const MIN_STRENGTH: f32 = 0.1;
+// 低频(底鼓、贝斯)的变化更像节拍, 加大权重
+const BASS_CUTOFF_HZ: f64 = 200.0;
+const BASS_WEIGHT: f32 = 4.0;
+
+#[derive(serde::Serialize)]
+struct BeatMap {
+    // 每个节拍的时间(秒)和强度(0~1)
+    beats: Vec<(f64, f64)>,
+    envelope_interval: f64,
+    // 按固定间隔采样的音量包络(0~1)
+    envelope: Vec<f64>,
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let Some(input) = args.next().map(PathBuf::from) else {
+        eprintln!("usage: beat_map <music file> [output file]");
+        exit(1);
+    };
+    let output = args
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| input.with_extension("beats.toml"));
+
+    let (samples, sample_rate) = match decode(&input) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            eprintln!("cannot decode {}: {}", input.display(), e);
+            exit(1);
+        }
+    };
+    if samples.len() < FRAME_SIZE {
+        eprintln!("{} is too short", input.display());
+        exit(1);
+    }
+
+    let beat_map = BeatMap {
+        beats: beats(&samples, sample_rate),
+        envelope_interval: ENVELOPE_INTERVAL,
+        envelope: envelope(&samples, sample_rate),
+    };
+    let s = toml::to_string(&beat_map).unwrap();
+    if let Err(e) = write(&output, s) {
+        eprintln!("cannot write {}: {}", output.display(), e);
+        exit(1);
+    }
+    println!(
+        "{} beats, {:.2}s -> {}",
+        beat_map.beats.len(),
+        samples.len() as f64 / sample_rate,
+        output.display()
+    );
+}
+
+// 解码并混成单声道, 按峰值归一化到 -1~1
+fn decode(path: &Path) -> Result<(Vec<f32>, f64), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let decoder = Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+    let channels = decoder.channels().max(1) as usize;
+    let sample_rate = decoder.sample_rate() as f64;
+    let interleaved: Vec<f32> = decoder.collect();
+    let mut samples: Vec<f32> = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    let peak = samples.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+    if peak > 0.0 {
+        samples.iter_mut().for_each(|s| *s /= peak);
+    }
+    Ok((samples, sample_rate))
+}
+
+// 每个间隔内的均方根音量, 按最大值归一化
+fn envelope(samples: &[f32], sample_rate: f64) -> Vec<f64> {
+    let chunk = ((ENVELOPE_INTERVAL * sample_rate) as usize).max(1);
+    let rms: Vec<f32> = samples
+        .chunks(chunk)
+        .map(|c| (c.iter().map(|s| s * s).sum::<f32>() / c.len() as f32).sqrt())
+        .collect();
+    let max = rms.iter().fold(0.0f32, |m, v| m.max(*v));
+    rms.iter()
+        .map(|v| {
+            if max > 0.0 {
+                round3((v / max) as f64)
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+// 频谱通量超过自适应阈值的局部峰值即为节拍
+fn beats(samples: &[f32], sample_rate: f64) -> Vec<(f64, f64)> {
+    let flux = spectral_flux(samples, sample_rate);
+    let frame_time = |i: usize| (i * HOP_SIZE + FRAME_SIZE / 2) as f64 / sample_rate;
+    let window = (THRESHOLD_WINDOW * sample_rate / HOP_SIZE as f64) as usize;
+
+    let mut peaks = Vec::new();
+    let mut last_t = f64::NEG_INFINITY;
+    for i in 0..flux.len().saturating_sub(1) {
+        let lo = i.saturating_sub(window);
+        let hi = (i + window + 1).min(flux.len());
+        let mean = flux[lo..hi].iter().sum::<f32>() / (hi - lo) as f32;
+        let is_peak = (i == 0 || flux[i] > flux[i - 1]) && flux[i] >= flux[i + 1];
+        let t = frame_time(i);
+        if is_peak && flux[i] > mean * THRESHOLD_SCALE && t - last_t >= MIN_BEAT_GAP {
+            peaks.push((t, flux[i]));
+            last_t = t;
+        }
+    }
+
+    let max = peaks.iter().fold(0.0f32, |m, (_, f)| m.max(*f));
+    peaks
+        .into_iter()
+        .map(|(t, f)| (round3(t), round3((f / max).max(MIN_STRENGTH) as f64)))
+        .collect()
+}
+
+// 相邻帧幅度谱的正向差之和, 衡量新出现的声音
+fn spectral_flux(samples: &[f32], sample_rate: f64) -> Vec<f32> {
+    let bass_bins = (BASS_CUTOFF_HZ * FRAME_SIZE as f64 / sample_rate) as usize;
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(FRAME_SIZE);
+    let hann: Vec<f32> = (0..FRAME_SIZE)
+        .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FRAME_SIZE as f32).cos())
+        .collect();
+
+    let mut flux = Vec::new();
+    let mut prev = vec![0.0f32; FRAME_SIZE / 2];
+    let mut buf = vec![Complex::new(0.0, 0.0); FRAME_SIZE];
+    for frame in samples.windows(FRAME_SIZE).step_by(HOP_SIZE) {
+        for ((c, s), w) in buf.iter_mut().zip(frame).zip(&hann) {
+            *c = Complex::new(s * w, 0.0);
+        }
+        fft.process(&mut buf);
+        let mut sum = 0.0;
+        for (k, (p, c)) in prev.iter_mut().zip(&buf).enumerate() {
+            let mag = c.norm();
+            let weight = if k < bass_bins { BASS_WEIGHT } else { 1.0 };
+            sum += (mag - *p).max(0.0) * weight;
+            *p = mag;
+        }
+        flux.push(sum);
+    }
+    flux
+}
+
+// 写文件时保留三位小数
+fn round3(v: f64) -> f64 {
+    (v * 1000.0).round() / 1000.0
+}
@@ -6,6 +6,10 @@ use std::{
     path::Path,
 };
 
+#[path = "../src/shared.rs"]
+mod shared;
+use shared::VIRTUAL_HEIGHT;
+
 const EDGE_THRESHOLD: f32 = 10.0;
 const WINDOW_SIZE_X: f32 = 1280.0;
 const WINDOW_SIZE_Y: f32 = 720.0;
@@ -34,6 +38,19 @@ struct EditTri {
     is_editing: Option<EditOptionTri>,
 }
 
+struct EditPoly {
+    points: Vec<egui::Pos2>,
+    is_editing: Option<EditOptionPoly>,
+}
+
+// 旋转矩形, 保存为 [中心 x, 中心 y, 半宽, 半高, 弧度]
+struct EditRotRect {
+    center: egui::Pos2,
+    half_size: egui::Vec2,
+    angle: f32,
+    is_editing: Option<EditOptionRotRect>,
+}
+
 struct EditCircle {
     circle_pos: egui::Pos2,
     radius: f32,
@@ -48,6 +65,12 @@ enum EditItem {
     RectFlyBegin(EditRect),
     RectFlyEnd(EditRect),
     Pass(EditRect),
+    PolyObstacle(EditPoly),
+    PolyFloor(EditPoly),
+    Slope(EditPoly),
+    Coin(EditCircle),
+    RotFloor(EditRotRect),
+    RotObstacle(EditRotRect),
 }
 
 impl Default for EditRect {
@@ -73,6 +96,44 @@ impl Default for EditTri {
     }
 }
 
+impl Default for EditPoly {
+    fn default() -> Self {
+        Self {
+            points: vec![
+                egui::Pos2::new(100.0, 200.0),
+                egui::Pos2::new(200.0, 200.0),
+                egui::Pos2::new(220.0, 140.0),
+                egui::Pos2::new(120.0, 100.0),
+            ],
+            is_editing: None,
+        }
+    }
+}
+
+impl EditPoly {
+    fn slope() -> Self {
+        Self {
+            points: vec![
+                egui::Pos2::new(100.0, 200.0),
+                egui::Pos2::new(250.0, 200.0),
+                egui::Pos2::new(250.0, 120.0),
+            ],
+            is_editing: None,
+        }
+    }
+}
+
+impl Default for EditRotRect {
+    fn default() -> Self {
+        Self {
+            center: egui::Pos2::new(150.0, 150.0),
+            half_size: egui::Vec2::new(50.0, 20.0),
+            angle: std::f32::consts::PI / 12.0,
+            is_editing: None,
+        }
+    }
+}
+
 impl Default for EditCircle {
     fn default() -> Self {
         Self {
@@ -86,11 +147,54 @@ impl Default for EditCircle {
 #[derive(Default)]
 struct LevelEditor {
     items: Vec<EditItem>,
+    // 与 items 一一对应的道具标记表(pulse、channel 等), 编辑器不修改, 原样写回
+    // 新建的道具没有对应项
+    flags: Vec<Option<toml::Table>>,
+    // 编辑器不处理的关卡元数据(镜头范围等), 原样写回
+    meta: toml::Table,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct LevelData {
-    data: Vec<(u32, Vec<f32>)>,
+    data: Vec<ItemData>,
+    #[serde(flatten)]
+    meta: toml::Table,
+}
+
+// 道具可以在类型和参数后面带一个标记表, 如 [2, [0, 0, 50, 50], { pulse = true }]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum ItemData {
+    Plain(u32, Vec<f32>),
+    Flagged(u32, Vec<f32>, toml::Table),
+}
+
+impl ItemData {
+    fn new(typ: u32, params: Vec<f32>, flags: Option<toml::Table>) -> Self {
+        match flags {
+            Some(flags) => Self::Flagged(typ, params, flags),
+            None => Self::Plain(typ, params),
+        }
+    }
+
+    fn parts(&self) -> (&u32, &Vec<f32>) {
+        match self {
+            Self::Plain(typ, params) | Self::Flagged(typ, params, _) => (typ, params),
+        }
+    }
+
+    fn parts_mut(&mut self) -> (&mut u32, &mut Vec<f32>) {
+        match self {
+            Self::Plain(typ, params) | Self::Flagged(typ, params, _) => (typ, params),
+        }
+    }
+
+    fn flags(&self) -> Option<&toml::Table> {
+        match self {
+            Self::Plain(..) => None,
+            Self::Flagged(_, _, flags) => Some(flags),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -109,6 +213,18 @@ enum EditOptionTri {
     Right,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditOptionPoly {
+    Vertex(usize),
+    Pos(egui::Vec2),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditOptionRotRect {
+    Corner,
+    Pos(egui::Vec2),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum EditOptionCircle {
     Pos(egui::Vec2),
@@ -121,8 +237,26 @@ impl LevelEditor {
         if let Ok(data_str) = data_str_res {
             let lv_data: LevelData = toml::from_str(&data_str).unwrap();
             let mut items = Vec::new();
-            for (typ, i) in lv_data.data.iter() {
-                if i.len() == 6 {
+            let mut flags = Vec::new();
+            for item in lv_data.data.iter() {
+                let (typ, i) = item.parts();
+                let items_len = items.len();
+                if *typ == 7 || *typ == 8 || *typ == 9 {
+                    let poly = EditPoly {
+                        points: i
+                            .chunks_exact(2)
+                            .map(|p| egui::Pos2::new(p[0], p[1]))
+                            .collect(),
+                        is_editing: None,
+                    };
+                    if *typ == 7 {
+                        items.push(EditItem::PolyObstacle(poly));
+                    } else if *typ == 9 {
+                        items.push(EditItem::Slope(poly));
+                    } else {
+                        items.push(EditItem::PolyFloor(poly));
+                    }
+                } else if i.len() == 6 {
                     let tri = EditTri {
                         tri_points: [
                             egui::Pos2::new(i[0], i[1]),
@@ -136,6 +270,18 @@ impl LevelEditor {
                     } else {
                         panic!();
                     }
+                } else if i.len() == 5 {
+                    let rect = EditRotRect {
+                        center: egui::Pos2::new(i[0], i[1]),
+                        half_size: egui::Vec2::new(i[2], i[3]),
+                        angle: i[4],
+                        is_editing: None,
+                    };
+                    if *typ == 2 {
+                        items.push(EditItem::RotObstacle(rect));
+                    } else {
+                        items.push(EditItem::RotFloor(rect));
+                    }
                 } else if i.len() == 4 {
                     let rect = EditRect {
                         rect_pos: egui::Pos2 { x: i[0], y: i[1] },
@@ -161,12 +307,21 @@ impl LevelEditor {
                     };
                     if *typ == 3 {
                         items.push(EditItem::DoubleJump(circle));
+                    } else if *typ == 10 {
+                        items.push(EditItem::Coin(circle));
                     } else {
                         panic!();
                     }
                 }
+                if items.len() > items_len {
+                    flags.push(item.flags().cloned());
+                }
+            }
+            Self {
+                items,
+                flags,
+                meta: lv_data.meta,
             }
-            Self { items }
         } else {
             return Self::default();
         }
@@ -174,12 +329,9 @@ impl LevelEditor {
 }
 
 impl EditCircle {
-    fn spawn_circle(&mut self, ui: &mut egui::Ui) -> bool {
-        ui.painter().circle_stroke(
-            self.circle_pos,
-            self.radius,
-            egui::Stroke::new(2.0, egui::Color32::WHITE),
-        );
+    fn spawn_circle(&mut self, ui: &mut egui::Ui, color: egui::Color32) -> bool {
+        ui.painter()
+            .circle_stroke(self.circle_pos, self.radius, egui::Stroke::new(2.0, color));
 
         if ui.input(|i| i.key_pressed(egui::Key::ArrowLeft)) {
             self.circle_pos.x -= 10.0; // 按下左键，向左移动
@@ -437,6 +589,215 @@ impl EditTri {
     }
 }
 
+impl EditPoly {
+    fn center(&self) -> egui::Pos2 {
+        let sum = self
+            .points
+            .iter()
+            .fold(egui::Vec2::ZERO, |acc, p| acc + p.to_vec2());
+        (sum / self.points.len() as f32).to_pos2()
+    }
+
+    // 按绕中心的角度排序, 保证顶点顺序是凸包的绕行顺序
+    fn sort_points(&mut self) {
+        let c = self.center();
+        self.points.sort_by(|a, b| {
+            let aa = (a.y - c.y).atan2(a.x - c.x);
+            let ab = (b.y - c.y).atan2(b.x - c.x);
+            aa.partial_cmp(&ab).unwrap()
+        });
+    }
+
+    fn rotate(&mut self, angle: f32) {
+        let c = self.center();
+        let rot = egui::emath::Rot2::from_angle(angle);
+        for p in self.points.iter_mut() {
+            *p = c + rot * (*p - c);
+        }
+    }
+
+    fn spawn_poly(&mut self, ui: &mut egui::Ui, color: egui::Color32) -> bool {
+        ui.painter().add(egui::Shape::convex_polygon(
+            self.points.clone(),
+            color,
+            egui::Stroke::new(2.0, egui::Color32::BLACK),
+        ));
+        for p in self.points.iter() {
+            ui.painter().circle_filled(*p, 4.0, egui::Color32::WHITE);
+        }
+
+        if ui.input(|i| i.key_pressed(egui::Key::ArrowLeft)) {
+            for i in self.points.iter_mut() {
+                i.x -= 10.0;
+            }
+        }
+        if ui.input(|i| i.key_pressed(egui::Key::ArrowRight)) {
+            for i in self.points.iter_mut() {
+                i.x += 10.0;
+            }
+        }
+
+        let mouse_pos = ui.input(|i| i.pointer.interact_pos());
+        if let Some(mouse_pos) = mouse_pos {
+            let vertex = self
+                .points
+                .iter()
+                .position(|p| p.distance(mouse_pos) < EDGE_THRESHOLD);
+            let inside = egui::Rect::from_points(&self.points).contains(mouse_pos);
+
+            let mut hold = None;
+            if self.is_editing.is_none() {
+                if let Some(j) = vertex {
+                    hold = Some(EditOptionPoly::Vertex(j));
+                } else if inside {
+                    hold = Some(EditOptionPoly::Pos(mouse_pos - self.points[0]));
+                }
+            }
+            ui.input(|i| {
+                if i.pointer.button_pressed(egui::PointerButton::Primary) {
+                    self.is_editing = hold;
+                }
+                if i.pointer.button_released(egui::PointerButton::Primary) {
+                    if self.is_editing.is_some() {
+                        self.sort_points();
+                    }
+                    self.is_editing = None;
+                }
+                // 右键: 点在顶点上删除顶点, 否则在多边形范围内添加顶点
+                if i.pointer.button_pressed(egui::PointerButton::Secondary) {
+                    if let Some(j) = vertex {
+                        if self.points.len() > 3 {
+                            self.points.remove(j);
+                        }
+                    } else if inside {
+                        self.points.push(mouse_pos);
+                        self.sort_points();
+                    }
+                }
+                // Q/E 旋转
+                if inside && i.key_pressed(egui::Key::Q) {
+                    self.rotate(-std::f32::consts::PI / 36.0);
+                }
+                if inside && i.key_pressed(egui::Key::E) {
+                    self.rotate(std::f32::consts::PI / 36.0);
+                }
+            });
+
+            if let Some(edge) = self.is_editing {
+                match edge {
+                    EditOptionPoly::Vertex(j) => {
+                        self.points[j] = mouse_pos;
+                    }
+                    EditOptionPoly::Pos(move_fix) => {
+                        let offset = mouse_pos - move_fix - self.points[0];
+                        for p in self.points.iter_mut() {
+                            *p += offset;
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.points.iter().all(|p| p.y < DROP_AREA_Y) {
+            return false;
+        }
+        return true;
+    }
+}
+
+impl EditRotRect {
+    fn corners(&self) -> Vec<egui::Pos2> {
+        let rot = egui::emath::Rot2::from_angle(self.angle);
+        [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .iter()
+            .map(|(sx, sy)| {
+                self.center + rot * egui::Vec2::new(sx * self.half_size.x, sy * self.half_size.y)
+            })
+            .collect()
+    }
+
+    fn to_vec(&self) -> Vec<f32> {
+        vec![
+            self.center.x,
+            self.center.y,
+            self.half_size.x,
+            self.half_size.y,
+            self.angle,
+        ]
+    }
+
+    // 鼠标位置转到矩形自身的坐标系
+    fn to_local(&self, pos: egui::Pos2) -> egui::Vec2 {
+        egui::emath::Rot2::from_angle(self.angle).inverse() * (pos - self.center)
+    }
+
+    fn spawn_rot_rect(&mut self, ui: &mut egui::Ui, color: egui::Color32) -> bool {
+        let corners = self.corners();
+        ui.painter().add(egui::Shape::closed_line(
+            corners.clone(),
+            egui::Stroke::new(2.0, color),
+        ));
+        for p in corners.iter() {
+            ui.painter().circle_filled(*p, 4.0, egui::Color32::WHITE);
+        }
+
+        if ui.input(|i| i.key_pressed(egui::Key::ArrowLeft)) {
+            self.center.x -= 10.0;
+        }
+        if ui.input(|i| i.key_pressed(egui::Key::ArrowRight)) {
+            self.center.x += 10.0;
+        }
+
+        let mouse_pos = ui.input(|i| i.pointer.interact_pos());
+        if let Some(mouse_pos) = mouse_pos {
+            let local = self.to_local(mouse_pos);
+            let on_corner = corners.iter().any(|p| p.distance(mouse_pos) < EDGE_THRESHOLD);
+            let inside = local.x.abs() < self.half_size.x && local.y.abs() < self.half_size.y;
+
+            let mut hold = None;
+            if self.is_editing.is_none() {
+                if on_corner {
+                    hold = Some(EditOptionRotRect::Corner);
+                } else if inside {
+                    hold = Some(EditOptionRotRect::Pos(mouse_pos - self.center));
+                }
+            }
+            ui.input(|i| {
+                if i.pointer.button_pressed(egui::PointerButton::Primary) {
+                    self.is_editing = hold;
+                }
+                if i.pointer.button_released(egui::PointerButton::Primary) {
+                    self.is_editing = None;
+                }
+                // Q/E 旋转
+                if inside && i.key_pressed(egui::Key::Q) {
+                    self.angle -= std::f32::consts::PI / 36.0;
+                }
+                if inside && i.key_pressed(egui::Key::E) {
+                    self.angle += std::f32::consts::PI / 36.0;
+                }
+            });
+
+            if let Some(edge) = self.is_editing {
+                match edge {
+                    // 拖动角点时以中心为基准缩放
+                    EditOptionRotRect::Corner => {
+                        self.half_size = local.abs().max(egui::Vec2::splat(EDGE_THRESHOLD));
+                    }
+                    EditOptionRotRect::Pos(move_fix) => {
+                        self.center = mouse_pos - move_fix;
+                    }
+                }
+            }
+        }
+
+        if self.center.y < DROP_AREA_Y {
+            return false;
+        }
+        return true;
+    }
+}
+
 impl eframe::App for LevelEditor {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -486,14 +847,50 @@ impl eframe::App for LevelEditor {
                 self.items.push(EditItem::RectFlyEnd(rect));
             }
 
+            if ui.button("spawn poly obstacle").clicked() {
+                let poly = EditPoly::default();
+                self.items.push(EditItem::PolyObstacle(poly));
+            }
+
+            if ui.button("spawn poly floor").clicked() {
+                let poly = EditPoly::default();
+                self.items.push(EditItem::PolyFloor(poly));
+            }
+
+            if ui.button("spawn slope").clicked() {
+                let poly = EditPoly::slope();
+                self.items.push(EditItem::Slope(poly));
+            }
+
+            if ui.button("spawn coin").clicked() {
+                let circle = EditCircle {
+                    radius: 15.0,
+                    ..Default::default()
+                };
+                self.items.push(EditItem::Coin(circle));
+            }
+
+            if ui.button("spawn rotated floor").clicked() {
+                let rect = EditRotRect::default();
+                self.items.push(EditItem::RotFloor(rect));
+            }
+
+            if ui.button("spawn rotated obstacle").clicked() {
+                let rect = EditRotRect::default();
+                self.items.push(EditItem::RotObstacle(rect));
+            }
+
             if ui.button("spawn level pass").clicked() {
                 let rect = EditRect::default();
                 self.items.push(EditItem::Pass(rect));
             }
 
-            let mut lv_data_ori = LevelData { data: Vec::new() };
+            let mut lv_data_ori = LevelData {
+                data: Vec::new(),
+                meta: self.meta.clone(),
+            };
             if ui.button("save data").clicked() {
-                for item in self.items.iter() {
+                for (idx, item) in self.items.iter().enumerate() {
                     let mut vt = Vec::new();
                     let typ;
                     let mut rec = &EditRect::default();
@@ -525,25 +922,62 @@ impl eframe::App for LevelEditor {
                                 vt.push(i.y);
                             }
                         }
+                        EditItem::PolyObstacle(poly) => {
+                            typ = 7;
+                            for i in poly.points.iter() {
+                                vt.push(i.x);
+                                vt.push(i.y);
+                            }
+                        }
+                        EditItem::PolyFloor(poly) => {
+                            typ = 8;
+                            for i in poly.points.iter() {
+                                vt.push(i.x);
+                                vt.push(i.y);
+                            }
+                        }
+                        EditItem::Slope(poly) => {
+                            typ = 9;
+                            for i in poly.points.iter() {
+                                vt.push(i.x);
+                                vt.push(i.y);
+                            }
+                        }
                         EditItem::DoubleJump(circle) => {
                             typ = 3;
                             vt.push(circle.circle_pos.x);
                             vt.push(circle.circle_pos.y);
                             vt.push(circle.radius);
                         }
+                        EditItem::Coin(circle) => {
+                            typ = 10;
+                            vt.push(circle.circle_pos.x);
+                            vt.push(circle.circle_pos.y);
+                            vt.push(circle.radius);
+                        }
+                        EditItem::RotFloor(rect) => {
+                            typ = 0;
+                            vt = rect.to_vec();
+                        }
+                        EditItem::RotObstacle(rect) => {
+                            typ = 2;
+                            vt = rect.to_vec();
+                        }
                     }
-                    if typ != 1 && typ != 3 {
+                    // 其余道具都已写入参数, 只剩普通矩形
+                    if vt.is_empty() {
                         vt.push(rec.rect_pos.x);
                         vt.push(rec.rect_pos.y);
                         vt.push(rec.rect_size.x);
                         vt.push(rec.rect_size.y);
                     }
-                    lv_data_ori.data.push((typ as u32, vt));
+                    let flags = self.flags.get(idx).cloned().flatten();
+                    lv_data_ori.data.push(ItemData::new(typ as u32, vt, flags));
                 }
 
                 lv_data_ori
                     .data
-                    .sort_by(|a, b| a.1[0].partial_cmp(&b.1[0]).unwrap());
+                    .sort_by(|a, b| a.parts().1[0].partial_cmp(&b.parts().1[0]).unwrap());
 
                 let mut file_ori = OpenOptions::new()
                     .create(true)
@@ -553,6 +987,17 @@ impl eframe::App for LevelEditor {
                     .unwrap();
                 let s = toml::to_string(&lv_data_ori).unwrap();
                 let _ = file_ori.write_all(s.as_bytes());
+                // new.toml 里手改过的元数据(音乐、主题、偏移等)优先, 只补上它缺少的键
+                if let Some(mut game_meta) = read_to_string("level_data/new.toml")
+                    .ok()
+                    .and_then(|s| toml::from_str::<toml::Table>(&s).ok())
+                {
+                    game_meta.remove("data");
+                    for (k, v) in lv_data_ori.meta {
+                        game_meta.entry(k).or_insert(v);
+                    }
+                    lv_data_ori.meta = game_meta;
+                }
                 let mut file = OpenOptions::new()
                     .create(true)
                     .truncate(true)
@@ -597,8 +1042,38 @@ impl eframe::App for LevelEditor {
                             drop_idx = i as i32;
                         }
                     }
+                    EditItem::PolyObstacle(poly) => {
+                        if !poly.spawn_poly(ui, egui::Color32::LIGHT_RED) {
+                            drop_idx = i as i32;
+                        }
+                    }
+                    EditItem::PolyFloor(poly) => {
+                        if !poly.spawn_poly(ui, egui::Color32::GRAY) {
+                            drop_idx = i as i32;
+                        }
+                    }
+                    EditItem::Slope(poly) => {
+                        if !poly.spawn_poly(ui, egui::Color32::DARK_GREEN) {
+                            drop_idx = i as i32;
+                        }
+                    }
                     EditItem::DoubleJump(circle) => {
-                        if !circle.spawn_circle(ui) {
+                        if !circle.spawn_circle(ui, egui::Color32::WHITE) {
+                            drop_idx = i as i32;
+                        }
+                    }
+                    EditItem::Coin(circle) => {
+                        if !circle.spawn_circle(ui, egui::Color32::GOLD) {
+                            drop_idx = i as i32;
+                        }
+                    }
+                    EditItem::RotFloor(rect) => {
+                        if !rect.spawn_rot_rect(ui, egui::Color32::WHITE) {
+                            drop_idx = i as i32;
+                        }
+                    }
+                    EditItem::RotObstacle(rect) => {
+                        if !rect.spawn_rot_rect(ui, egui::Color32::RED) {
                             drop_idx = i as i32;
                         }
                     }
@@ -606,25 +1081,43 @@ impl eframe::App for LevelEditor {
             }
             if drop_idx >= 0 {
                 self.items.remove(drop_idx as usize);
+                if (drop_idx as usize) < self.flags.len() {
+                    self.flags.remove(drop_idx as usize);
+                }
             }
         });
     }
 }
 
+// 编辑区 y 轴向下、原点在顶部, 游戏 y 轴向上、原点在可视区中心
+// 编辑区按游戏镜头显示的世界高度换算到游戏坐标
+fn egui_y2bevy(y: f32) -> f32 {
+    VIRTUAL_HEIGHT / 2.0 - y
+}
+
 fn egui2bevy(ld: &mut LevelData) {
-    for (_, i) in ld.data.iter_mut() {
-        if i.len() == 4 {
+    for item in ld.data.iter_mut() {
+        let (typ, i) = item.parts_mut();
+        if matches!(*typ, 7..=9) {
+            for y in i.iter_mut().skip(1).step_by(2) {
+                *y = egui_y2bevy(*y);
+            }
+        } else if i.len() == 5 {
+            // 编辑区 y 轴向下, 翻转后旋转方向相反
+            i[1] = egui_y2bevy(i[1]);
+            i[4] = -i[4];
+        } else if i.len() == 4 {
             i[0] = i[0] + i[2] / 2.0;
             i[1] = i[1] + i[3] / 2.0;
-            i[1] = 720.0 - i[1] - 360.0;
+            i[1] = egui_y2bevy(i[1]);
             i[2] /= 2.0;
             i[3] /= 2.0;
         } else if i.len() == 6 {
-            i[1] = 720.0 - i[1] - 360.0;
-            i[3] = 720.0 - i[3] - 360.0;
-            i[5] = 720.0 - i[5] - 360.0;
+            i[1] = egui_y2bevy(i[1]);
+            i[3] = egui_y2bevy(i[3]);
+            i[5] = egui_y2bevy(i[5]);
         } else if i.len() == 3 {
-            i[1] = 720.0 - i[1] - 360.0;
+            i[1] = egui_y2bevy(i[1]);
         } else {
             panic!();
         }
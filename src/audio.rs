@@ -0,0 +1,69 @@
+use bevy::audio::Volume;
+use bevy::prelude::*;
+
+use std::time::Duration;
+
+use crate::settings::Settings;
+use crate::theme::SfxHandles;
+use crate::types::*;
+
+// 进入 Playing 时播放关卡音乐: 暂停回来时继续, 否则从 MusicTime 开始新播一遍
+pub fn start_music(
+    mut cmd: Commands,
+    level_data: Res<LevelData>,
+    music_time: Res<MusicTime>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+    mut music: Query<&mut AudioSink, With<LevelMusic>>,
+) {
+    let volume = Volume::Linear(settings.music_volume);
+    if let Ok(mut sink) = music.single_mut() {
+        sink.set_volume(volume);
+        sink.play();
+        return;
+    }
+    let Some(path) = &level_data.music else {
+        return;
+    };
+    info!("play music {} from {:.2}s", path, music_time.0);
+    cmd.spawn((
+        AudioPlayer::new(asset_server.load(path.as_str())),
+        PlaybackSettings::ONCE
+            .with_volume(volume)
+            .with_start_position(Duration::from_secs_f32(music_time.0.max(0.0))),
+        LevelMusic,
+        LevelDecor,
+    ));
+}
+
+pub fn pause_music(music: Query<&AudioSink, With<LevelMusic>>) {
+    for sink in music.iter() {
+        sink.pause();
+    }
+}
+
+// 死亡时立即停掉, 重开后从头(或检查点)重新播放
+pub fn stop_music(mut cmd: Commands, music: Query<Entity, With<LevelMusic>>) {
+    for entity in music.iter() {
+        cmd.entity(entity).despawn();
+    }
+}
+
+// 按游戏事件播放主题里对应的音效, 音量为总音效音量乘以分类音量
+pub fn play_sfx(
+    mut cmd: Commands,
+    mut game_events: MessageReader<GameEvent>,
+    sfx: Res<SfxHandles>,
+    settings: Res<Settings>,
+) {
+    for event in game_events.read() {
+        let Some(handle) = sfx.0.get(event) else {
+            continue;
+        };
+        let volume = settings.sfx_volume * settings.category_volume(event.category());
+        cmd.spawn((
+            AudioPlayer::new(handle.clone()),
+            PlaybackSettings::DESPAWN.with_volume(Volume::Linear(volume)),
+        ));
+    }
+}
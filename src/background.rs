@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+
+use crate::theme::parse_color;
+use crate::types::*;
+
+// 进入关卡时生成背景层, 颜色触发器从头开始
+pub fn background_init(
+    mut cmd: Commands,
+    level_data: Res<LevelData>,
+    asset_server: Res<AssetServer>,
+    mut trigger_state: ResMut<ColorTriggerState>,
+) {
+    for (index, layer) in level_data.background.iter().enumerate() {
+        let image_mode = if layer.tile_x || layer.tile_y {
+            SpriteImageMode::Tiled {
+                tile_x: layer.tile_x,
+                tile_y: layer.tile_y,
+                stretch_value: layer.scale,
+            }
+        } else {
+            SpriteImageMode::Auto
+        };
+        cmd.spawn((
+            Sprite {
+                image: asset_server.load(layer.image.as_str()),
+                color: parse_color(layer.tint.as_deref()),
+                image_mode,
+                ..default()
+            },
+            Transform::from_xyz(layer.x, layer.y, BACKGROUND_Z + index as f32),
+            BackgroundLayer { index, fade: None },
+            LevelDecor,
+        ));
+    }
+    *trigger_state = ColorTriggerState {
+        next: 0,
+        instant: true,
+    };
+}
+
+// 单个方向上的位置和大小: 平铺时铺满可视范围并按贴图大小取余, 否则按原尺寸
+fn parallax_axis(
+    camera: f32,
+    anchor: f32,
+    scroll: f32,
+    view: f32,
+    image: f32,
+    tile: bool,
+) -> (f32, f32) {
+    let offset = (anchor - camera) * scroll;
+    if tile && image > 0.0 {
+        (camera + offset.rem_euclid(image), view + image * 2.0)
+    } else {
+        (camera + offset, image)
+    }
+}
+
+// 背景层跟随镜头移动, 越远的层(scroll 越小)移动越慢
+pub fn update_background(
+    camera: Single<(&Transform, &Projection), With<Camera>>,
+    images: Res<Assets<Image>>,
+    level_data: Res<LevelData>,
+    mut layers: Query<(&BackgroundLayer, &mut Transform, &mut Sprite), Without<Camera>>,
+) {
+    let (camera_transform, projection) = camera.into_inner();
+    let Projection::Orthographic(ortho) = projection else {
+        return;
+    };
+    let view = ortho.area.size();
+    let camera_pos = camera_transform.translation.truncate();
+    for (layer, mut transform, mut sprite) in layers.iter_mut() {
+        let Some(data) = level_data.background.get(layer.index) else {
+            continue;
+        };
+        // 贴图加载完才知道尺寸
+        let Some(image) = images.get(&sprite.image) else {
+            continue;
+        };
+        let image_size = image.size_f32() * data.scale;
+        let (x, w) = parallax_axis(
+            camera_pos.x,
+            data.x,
+            data.scroll,
+            view.x,
+            image_size.x,
+            data.tile_x,
+        );
+        let (y, h) = parallax_axis(
+            camera_pos.y,
+            data.y,
+            data.scroll,
+            view.y,
+            image_size.y,
+            data.tile_y,
+        );
+        transform.translation.x = x;
+        transform.translation.y = y;
+        sprite.custom_size = Some(Vec2::new(w, h));
+    }
+}
+
+pub fn fade_layers(time: Res<Time>, mut layers: Query<(&mut BackgroundLayer, &mut Sprite)>) {
+    for (mut layer, mut sprite) in layers.iter_mut() {
+        let Some(fade) = layer.fade.as_mut() else {
+            continue;
+        };
+        fade.timer.tick(time.delta());
+        sprite.color = fade
+            .from
+            .to_srgba()
+            .mix(&fade.to.to_srgba(), fade.timer.fraction())
+            .into();
+        if fade.timer.is_finished() {
+            layer.fade = None;
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+
+use crate::types::*;
+
+// 按当前音乐时间查节拍文件, 没有节拍文件的关卡始终为 0
+pub fn update_beat_pulse(
+    level_data: Res<LevelData>,
+    music_time: Res<MusicTime>,
+    mut beat_pulse: ResMut<BeatPulse>,
+) {
+    beat_pulse.beat = level_data.beat_map.beat(music_time.0);
+    beat_pulse.amplitude = level_data.beat_map.amplitude(music_time.0);
+}
+
+// 离开游戏或死亡后画面恢复平静
+pub fn reset_beat_pulse(mut beat_pulse: ResMut<BeatPulse>) {
+    *beat_pulse = BeatPulse::default();
+}
+
+// 背景颜色为背景通道的颜色, 跟随音量变亮, 节拍上再额外亮一点
+pub fn pulse_background(
+    beat_pulse: Res<BeatPulse>,
+    channels: Res<ColorChannels>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    let t = beat_pulse
+        .amplitude
+        .max(beat_pulse.beat * 0.5)
+        .clamp(0.0, 1.0);
+    clear_color.0 = channels
+        .get(ColorChannel::Background)
+        .to_srgba()
+        .mix(&BACKGROUND_PULSE_COLOR.to_srgba(), t)
+        .into();
+}
+
+pub fn pulse_glow(beat_pulse: Res<BeatPulse>, mut glow: Query<&mut Transform, With<PlayerGlow>>) {
+    let scale = 1.0 + GLOW_PULSE_SCALE * beat_pulse.beat.clamp(0.0, 1.0);
+    for mut transform in glow.iter_mut() {
+        transform.scale = Vec3::splat(scale);
+    }
+}
@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+
+use crate::theme::parse_color;
+use crate::types::*;
+
+// 进入关卡时颜色通道恢复为关卡指定的初始颜色
+pub fn color_channels_init(level_data: Res<LevelData>, mut channels: ResMut<ColorChannels>) {
+    *channels = ColorChannels {
+        colors: level_data.colors.clone(),
+        fades: default(),
+    };
+}
+
+// 回到主菜单时背景恢复默认颜色
+pub fn reset_color_channels(mut channels: ResMut<ColorChannels>) {
+    *channels = ColorChannels::default();
+}
+
+// 角色经过颜色触发器时开始渐变
+pub fn color_triggers(
+    level_data: Res<LevelData>,
+    role: Single<&SimPosition>,
+    mut trigger_state: ResMut<ColorTriggerState>,
+    mut channels: ResMut<ColorChannels>,
+    mut layers: Query<(&mut BackgroundLayer, &mut Sprite)>,
+) {
+    let instant = trigger_state.instant;
+    trigger_state.instant = false;
+    while let Some(trigger) = level_data.color_triggers.get(trigger_state.next) {
+        if trigger.x > role.cur.x {
+            break;
+        }
+        trigger_state.next += 1;
+        let to = parse_color(Some(trigger.color.as_str()));
+        let fade = |from: Color| {
+            (!instant && trigger.duration > 0.0).then(|| ColorFade {
+                from,
+                to,
+                timer: Timer::from_seconds(trigger.duration, TimerMode::Once),
+            })
+        };
+        if let Some(channel) = trigger.channel {
+            match fade(channels.get(channel)) {
+                Some(fade) => {
+                    channels.fades.insert(channel, fade);
+                }
+                None => {
+                    channels.fades.remove(&channel);
+                    channels.colors.insert(channel, to);
+                }
+            }
+        }
+        for (mut layer, mut sprite) in layers.iter_mut() {
+            if Some(layer.index) != trigger.layer {
+                continue;
+            }
+            layer.fade = fade(sprite.color);
+            if layer.fade.is_none() {
+                sprite.color = to;
+            }
+        }
+    }
+}
+
+pub fn fade_channels(time: Res<Time>, mut channels: ResMut<ColorChannels>) {
+    if channels.fades.is_empty() {
+        return;
+    }
+    let ColorChannels { colors, fades } = &mut *channels;
+    fades.retain(|channel, fade| {
+        fade.timer.tick(time.delta());
+        let color = fade
+            .from
+            .to_srgba()
+            .mix(&fade.to.to_srgba(), fade.timer.fraction());
+        colors.insert(*channel, color.into());
+        !fade.timer.is_finished()
+    });
+}
+
+// 道具颜色 = 主题颜色 x 通道颜色, 跟随节拍的道具在两拍之间再变暗
+fn item_color(tint: Color, channel: Color, pulse: f32) -> Color {
+    let tint = tint.to_srgba();
+    let channel = channel.to_srgba();
+    Color::srgba(
+        tint.red * channel.red * pulse,
+        tint.green * channel.green * pulse,
+        tint.blue * channel.blue * pulse,
+        tint.alpha * channel.alpha,
+    )
+}
+
+pub fn tint_items(
+    beat_pulse: Res<BeatPulse>,
+    channels: Res<ColorChannels>,
+    mut sprites: Query<(&mut Sprite, &ItemTint, Option<&ItemChannel>, Has<Pulse>)>,
+    meshes: Query<(
+        &MeshMaterial2d<ColorMaterial>,
+        &ItemTint,
+        Option<&ItemChannel>,
+        Has<Pulse>,
+    )>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let pulse = PULSE_DIM + (1.0 - PULSE_DIM) * beat_pulse.beat.clamp(0.0, 1.0);
+    let color = |tint: &ItemTint, channel: Option<&ItemChannel>, has_pulse: bool| {
+        item_color(
+            tint.0,
+            channel.map_or(Color::WHITE, |c| channels.get(c.0)),
+            if has_pulse { pulse } else { 1.0 },
+        )
+    };
+    for (mut sprite, tint, channel, has_pulse) in sprites.iter_mut() {
+        let color = color(tint, channel, has_pulse);
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+    for (material, tint, channel, has_pulse) in meshes.iter() {
+        let color = color(tint, channel, has_pulse);
+        // 只在颜色变化时修改材质, 避免每帧重新上传
+        if materials.get(&material.0).is_some_and(|m| m.color != color)
+            && let Some(material) = materials.get_mut(&material.0)
+        {
+            material.color = color;
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
+use bevy::ecs::entity::Entities;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::types::*;
+
+// 按键打开或关闭左上角的调试信息
+pub fn toggle_debug_overlay(mut cmd: Commands, overlay: Query<Entity, With<DebugOverlayUIEntity>>) {
+    if let Ok(entity) = overlay.single() {
+        cmd.entity(entity).despawn();
+        return;
+    }
+    cmd.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(8.),
+            top: Val::Px(8.),
+            padding: UiRect::all(Val::Px(6.)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        GlobalZIndex(100),
+        DebugOverlayUIEntity,
+    ))
+    .with_children(|parent| {
+        parent.spawn((
+            Text::new(""),
+            TextFont {
+                font_size: 16.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.6, 1.0, 0.6)),
+            DebugOverlayLabel,
+        ));
+    });
+}
+
+// 碰撞体调试渲染临时开关, 不改设置里的显示碰撞框
+pub fn toggle_hitboxes(mut show_hitboxes: ResMut<ShowHitboxes>) {
+    show_hitboxes.0 = !show_hitboxes.0;
+}
+
+pub fn apply_hitboxes(
+    show_hitboxes: Res<ShowHitboxes>,
+    mut debug_render: ResMut<DebugRenderContext>,
+) {
+    debug_render.enabled = show_hitboxes.0;
+}
+
+pub fn debug_overlay_update(
+    diagnostics: Res<DiagnosticsStore>,
+    entities: &Entities,
+    lv_idx_entity_paires: Res<IdxEntityPair>,
+    level_data: Res<LevelData>,
+    role: Query<(&RoleState, &RoleSpeed, &SimPosition)>,
+    camera_transform: Single<&Transform, With<Camera>>,
+    mut label: Single<&mut Text, With<DebugOverlayLabel>>,
+) {
+    let fps = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+        .unwrap_or_default();
+    let mut lines = vec![
+        format!("fps: {:.0}", fps),
+        format!("entities: {}", entities.len()),
+        format!("spawned items: {}", lv_idx_entity_paires.pairs.len()),
+        format!("camera x: {:.1}", camera_transform.translation.x),
+    ];
+    if let Ok((state, speed, sim_pos)) = role.single() {
+        lines.push(format!("role state: {:?}", state));
+        lines.push(format!("role speed: ({:.1}, {:.1})", speed.0, speed.1));
+        // 前方最近的道具: 包围盒左边在角色右侧的道具中最近的一个
+        let upcoming = level_data
+            .data
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (i, item, item.aabb().0.x - sim_pos.cur.x))
+            .filter(|(_, _, distance)| *distance > 0.0)
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        lines.push(match upcoming {
+            Some((i, item, distance)) => {
+                format!("next item: #{} {} in {:.1}", i, item.name(), distance)
+            }
+            None => "next item: none".to_owned(),
+        });
+    }
+    label.0 = lines.join("\n");
+}
@@ -1,7 +1,14 @@
+use bevy::camera::ScalingMode;
+use bevy::ecs::schedule::ScheduleConfigs;
+use bevy::ecs::system::ScheduleSystem;
 use bevy::math::prelude::*;
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
+use crate::icon::player_icon;
+use crate::locale::Locale;
+use crate::save::SaveData;
+use crate::settings::Settings;
 use crate::types::*;
 
 fn spawn_floor(
@@ -9,7 +16,6 @@ fn spawn_floor(
     rect: &Vec4,
     index: u32,
     lv_idx_entity_paires: &mut ResMut<IdxEntityPair>,
-    asset_server: &Res<AssetServer>,
 ) {
     let hw = FLOOR_H;
     let hy = rect.y + rect.w - hw / 2.0;
@@ -18,19 +24,8 @@ fn spawn_floor(
         &Vec4::new(rect.x, rect.y - hw / 2.0, rect.z, rect.w - hw / 2.0),
         true,
     );
-    let id1 = cmd
-        .spawn(floor_high)
-        .insert(Sprite {
-            image: asset_server.load("grass.png"),
-            image_mode: SpriteImageMode::Tiled {
-                tile_x: true,
-                tile_y: false,
-                stretch_value: 1.0,
-            },
-            ..Default::default()
-        })
-        .id();
-    let id2 = cmd.spawn(floor_low).id();
+    let id1 = cmd.spawn((floor_high, ItemSkin::FloorTop)).id();
+    let id2 = cmd.spawn((floor_low, ItemSkin::Floor)).id();
 
     lv_idx_entity_paires.pairs.insert(index, (id1, Some(id2)));
     info!("spawn: entity {} {}", id1, id2);
@@ -43,7 +38,7 @@ fn spawn_rect_obstacle(
     lv_idx_entity_paires: &mut ResMut<IdxEntityPair>,
 ) {
     let rect_obstacle = MapItemBundle::rect_item(rect, true);
-    let id = cmd.spawn(rect_obstacle).id();
+    let id = cmd.spawn((rect_obstacle, ItemSkin::Block)).id();
 
     lv_idx_entity_paires.pairs.insert(index, (id, None));
     info!("spawn: entity {}", id);
@@ -56,7 +51,7 @@ fn spawn_rect_pass(
     lv_idx_entity_paires: &mut ResMut<IdxEntityPair>,
 ) {
     let rect_pass = MapItemBundle::rect_pass(rect);
-    let id = cmd.spawn(rect_pass).id();
+    let id = cmd.spawn((rect_pass, ItemSkin::Pass)).id();
 
     lv_idx_entity_paires.pairs.insert(index, (id, None));
     info!("spawn: entity {}", id);
@@ -70,7 +65,12 @@ fn spawn_rect_fly(
     begin: bool,
 ) {
     let rect_fly = MapItemBundle::rect_fly(rect, begin);
-    let id = cmd.spawn(rect_fly).insert(Sensor).id();
+    let skin = if begin {
+        ItemSkin::PortalBegin
+    } else {
+        ItemSkin::PortalEnd
+    };
+    let id = cmd.spawn((rect_fly, skin)).insert(Sensor).id();
 
     lv_idx_entity_paires.pairs.insert(index, (id, None));
     info!("spawn: entity {}", id);
@@ -82,7 +82,60 @@ fn spawn_tri_obstacle(
     index: u32,
     lv_idx_entity_paires: &mut ResMut<IdxEntityPair>,
 ) {
-    let id = cmd.spawn(MapItemBundle::tri_obstacle(tri)).id();
+    let id = cmd
+        .spawn((MapItemBundle::tri_obstacle(tri), ItemSkin::Spike))
+        .id();
+    lv_idx_entity_paires.pairs.insert(index, (id, None));
+    info!("sapwn: entity {}", id);
+}
+
+fn spawn_polygon(
+    cmd: &mut Commands,
+    points: &[Vec2],
+    obstacle: bool,
+    index: u32,
+    lv_idx_entity_paires: &mut ResMut<IdxEntityPair>,
+) {
+    let Some(polygon) = MapItemBundle::polygon(points, obstacle) else {
+        warn!("invalid polygon {}, skip", index);
+        return;
+    };
+    let skin = if obstacle {
+        ItemSkin::Obstacle
+    } else {
+        ItemSkin::Ground
+    };
+    let id = cmd.spawn((polygon, skin)).id();
+    lv_idx_entity_paires.pairs.insert(index, (id, None));
+    info!("spawn: entity {}", id);
+}
+
+fn spawn_slope(
+    cmd: &mut Commands,
+    points: &[Vec2],
+    index: u32,
+    lv_idx_entity_paires: &mut ResMut<IdxEntityPair>,
+) {
+    let Some(slope) = MapItemBundle::slope(points) else {
+        warn!("invalid slope {}, skip", index);
+        return;
+    };
+    let id = cmd.spawn((slope, ItemSkin::Slope)).id();
+    lv_idx_entity_paires.pairs.insert(index, (id, None));
+    info!("spawn: entity {}", id);
+}
+
+fn spawn_coin(
+    cmd: &mut Commands,
+    pos: &Vec2,
+    radius: f32,
+    index: u32,
+    lv_idx_entity_paires: &mut ResMut<IdxEntityPair>,
+) {
+    let id = cmd
+        .spawn((MapItemBundle::circle_coin(pos, radius), ItemSkin::Coin))
+        .insert(Sensor)
+        .id();
     lv_idx_entity_paires.pairs.insert(index, (id, None));
     info!("sapwn: entity {}", id);
 }
@@ -93,89 +146,80 @@ fn spawn_circle(
     radius: f32,
     index: u32,
     lv_idx_entity_paires: &mut ResMut<IdxEntityPair>,
-    _asset_server: &Res<AssetServer>,
 ) {
     let id = cmd
-        .spawn(MapItemBundle::circle_double_jump(pos, radius))
+        .spawn((
+            MapItemBundle::circle_double_jump(pos, radius),
+            ItemSkin::Orb,
+        ))
         .insert(Sensor)
         .id();
     lv_idx_entity_paires.pairs.insert(index, (id, None));
     info!("sapwn: entity {}", id);
 }
 
-// 随着镜头移动创建和销毁地图资源
+// 随着角色移动创建和销毁地图资源
+// 范围只由模拟位置决定, 不读渲染中平滑过的镜头, 哪些碰撞体存在和帧率无关
 pub fn dynamic_map_item(
     mut cmd: Commands,
     level_data: Res<LevelData>,
+    run_stats: Res<RunStats>,
     mut lv_idx_entity_paires: ResMut<IdxEntityPair>,
-    asset_server: Res<AssetServer>,
-    camera_transform: Single<&mut Transform, (With<Camera>, Without<RoleSpeed>, Without<MapItem>)>,
+    sim_pos: Single<&SimPosition>,
 ) {
-    let screen_half_x = (WINDOW_RESOLUTION_X / 2) as f32;
+    // 水平方向镜头固定在角色前方, 竖直方向镜头在死区内跟随角色
+    let camera_pos = Vec2::new(sim_pos.cur.x + CAMERA_OFFSET_X, sim_pos.cur.y);
+    let screen_half_x = CULL_HALF_X;
+    let screen_half_y = VIRTUAL_HEIGHT / 2.0 + CAMERA_DEAD_ZONE_Y;
 
-    type SpawnRectArgs<'a, 'b, 'c, 'd, 'e> = (
-        &'a mut Commands<'b, 'c>,
-        &'a mut ResMut<'d, IdxEntityPair>,
-        &'a Res<'e, AssetServer>,
-    );
+    type SpawnRectArgs<'a, 'b, 'c, 'd> =
+        (&'a mut Commands<'b, 'c>, &'a mut ResMut<'d, IdxEntityPair>);
 
     for (i, lv_data) in level_data.data.iter().enumerate() {
         let i = i as u32;
-        let (spawn_f, left, right): (Box<dyn Fn(SpawnRectArgs)>, _, _) = match lv_data {
-            MapItemData::RectFlyBegin(rect) => (
-                Box::new(|args: SpawnRectArgs| {
-                    spawn_rect_fly(args.0, rect, i, args.1, true);
-                }),
-                rect.x - rect.z,
-                rect.x + rect.z,
-            ),
-            MapItemData::RectFlyEnd(rect) => (
-                Box::new(|args: SpawnRectArgs| {
-                    spawn_rect_fly(args.0, rect, i, args.1, false);
-                }),
-                rect.x - rect.z,
-                rect.x + rect.z,
-            ),
-            MapItemData::RectObstacle(rect) => (
-                Box::new(|args: SpawnRectArgs| {
-                    spawn_rect_obstacle(args.0, rect, i, args.1);
-                }),
-                rect.x - rect.z,
-                rect.x + rect.z,
-            ),
-            MapItemData::RectPass(rect) => (
-                Box::new(|args: SpawnRectArgs| {
-                    spawn_rect_pass(args.0, rect, i, args.1);
-                }),
-                rect.x - rect.z,
-                rect.x + rect.z,
-            ),
-            MapItemData::Floor(rect) => (
-                Box::new(|args: SpawnRectArgs| {
-                    spawn_floor(args.0, rect, i, args.1, args.2);
-                }),
-                rect.x - rect.z,
-                rect.x + rect.z,
-            ),
-            MapItemData::TriObstacle(tri) => (
-                Box::new(|args: SpawnRectArgs| {
-                    spawn_tri_obstacle(args.0, tri, i, args.1);
-                }),
-                tri.vertices[0].x,
-                tri.vertices[2].x,
-            ),
-
-            MapItemData::DoubleJumpCircle(pos, radius) => (
-                Box::new(|args: SpawnRectArgs| {
-                    spawn_circle(args.0, pos, *radius, i, args.1, args.2);
-                }),
-                pos.x - radius,
-                pos.x + radius,
-            ),
+        if run_stats.collected.contains(&i) {
+            continue;
+        }
+        let spawn_f: Box<dyn Fn(SpawnRectArgs)> = match lv_data {
+            MapItemData::RectFlyBegin(rect) => Box::new(|args: SpawnRectArgs| {
+                spawn_rect_fly(args.0, rect, i, args.1, true);
+            }),
+            MapItemData::RectFlyEnd(rect) => Box::new(|args: SpawnRectArgs| {
+                spawn_rect_fly(args.0, rect, i, args.1, false);
+            }),
+            MapItemData::RectObstacle(rect) => Box::new(|args: SpawnRectArgs| {
+                spawn_rect_obstacle(args.0, rect, i, args.1);
+            }),
+            MapItemData::RectPass(rect) => Box::new(|args: SpawnRectArgs| {
+                spawn_rect_pass(args.0, rect, i, args.1);
+            }),
+            MapItemData::Floor(rect) => Box::new(|args: SpawnRectArgs| {
+                spawn_floor(args.0, rect, i, args.1);
+            }),
+            MapItemData::TriObstacle(tri) => Box::new(|args: SpawnRectArgs| {
+                spawn_tri_obstacle(args.0, tri, i, args.1);
+            }),
+            MapItemData::Polygon(points, obstacle) => Box::new(|args: SpawnRectArgs| {
+                spawn_polygon(args.0, points, *obstacle, i, args.1);
+            }),
+            MapItemData::Slope(points) => Box::new(|args: SpawnRectArgs| {
+                spawn_slope(args.0, points, i, args.1);
+            }),
+            MapItemData::DoubleJumpCircle(pos, radius) => Box::new(|args: SpawnRectArgs| {
+                spawn_circle(args.0, pos, *radius, i, args.1);
+            }),
+            MapItemData::Coin(pos, radius) => Box::new(|args: SpawnRectArgs| {
+                spawn_coin(args.0, pos, *radius, i, args.1);
+            }),
+        };
+        let (min, max) = lv_data.aabb();
+        let in_view_y = |margin: f32| {
+            max.y > camera_pos.y - screen_half_y - margin
+                && min.y < camera_pos.y + screen_half_y + margin
         };
 
         if let Some(entity_idx) = lv_idx_entity_paires.pairs.get(&i) {
-            if camera_transform.translation.x - right > screen_half_x {
+            if camera_pos.x - max.x > screen_half_x || !in_view_y(CULL_MARGIN_Y) {
                 cmd.entity(entity_idx.0).despawn();
                 info!("destroy entity {}", entity_idx.0);
                 if let Some(attach_entity) = entity_idx.1 {
@@ -185,9 +229,22 @@ pub fn dynamic_map_item(
                 lv_idx_entity_paires.pairs.remove(&i);
             }
         } else {
-            let coming_distance = left - camera_transform.translation.x;
-            if coming_distance > 0.0 && coming_distance < screen_half_x {
-                spawn_f((&mut cmd, &mut lv_idx_entity_paires, &asset_server));
+            let coming_distance = min.x - camera_pos.x;
+            let behind_distance = camera_pos.x - max.x;
+            if coming_distance < screen_half_x && behind_distance < screen_half_x && in_view_y(0.0)
+            {
+                spawn_f((&mut cmd, &mut lv_idx_entity_paires));
+                // 道具可能因为参数无效没有生成
+                if let Some((id, attach)) = lv_idx_entity_paires.pairs.get(&i) {
+                    for entity in std::iter::once(*id).chain(*attach) {
+                        if level_data.pulse.contains(&i) {
+                            cmd.entity(entity).insert(Pulse);
+                        }
+                        if let Some(channel) = level_data.channels.get(&i) {
+                            cmd.entity(entity).insert(ItemChannel(*channel));
+                        }
+                    }
+                }
             }
         }
     }
@@ -195,31 +252,144 @@ pub fn dynamic_map_item(
 
 pub fn game_init(
     mut cmd: Commands,
+    level_data: Res<LevelData>,
+    lvs: Res<CurLevel>,
+    mut stats: ResMut<SessionStats>,
+    mut run_stats: ResMut<RunStats>,
+    practice: Res<Practice>,
+    locale: Res<Locale>,
+    mut music_time: ResMut<MusicTime>,
+    mut jump_input: ResMut<JumpInput>,
+    save: Res<SaveData>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
     mut camera_transform: Single<
         &mut Transform,
         (With<Camera>, Without<RoleSpeed>, Without<MapItem>),
     >,
 ) {
     info!("game init");
-    //let block_texture = asset_server.load("block.png");
-    camera_transform.translation.x = 0.0;
-    camera_transform.translation.y = 0.0;
+    // 练习模式从最后一个检查点开始
+    let start = practice.checkpoints.last().copied().unwrap_or(Checkpoint {
+        pos: ROLE_START,
+        speed: Vec2::new(ROLE_SPEED, 0.0),
+        state: RoleState::Air(999),
+        camera_y: level_data.clamp_camera_y(0.0),
+        time: 0.0,
+    });
+    camera_transform.translation.x = start.pos.x + CAMERA_OFFSET_X;
+    camera_transform.translation.y = start.camera_y;
+    *run_stats = RunStats {
+        time: start.time,
+        ..default()
+    };
+    music_time.0 = level_data.music_offset + start.time;
+    // 上次尝试最后一帧按下的跳跃不带到这次
+    jump_input.0 = false;
+    for checkpoint in practice.checkpoints.iter() {
+        spawn_checkpoint_marker(&mut cmd, checkpoint.pos);
+    }
 
+    // 本次启动的最好进度从存档里的记录开始, 界面显示的不会低于存档
+    stats
+        .best_progress
+        .entry(lvs.cur_name())
+        .or_insert_with(|| save.record(&lvs.cur_name()).best_progress);
+    let attempt = stats.attempts.entry(lvs.cur_name()).or_insert(0);
+    *attempt += 1;
     cmd.spawn((
-        RigidBody::Dynamic,
-        Ccd::enabled(),
+        Text2d::new(locale.format("attempt", &[attempt.to_string()])),
+        TextFont {
+            font_size: 48.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.9, 0.9, 0.9)),
+        Transform::from_xyz(start.pos.x + 300.0, start.pos.y - 50.0, 1.0),
+        AttemptLabel,
+        LevelDecor,
+    ));
+
+    cmd.spawn((
+        role_bundle(&start),
+        children![(
+            Transform::default(),
+            Visibility::default(),
+            RoleVisual,
+            children![
+                (
+                    Mesh2d(meshes.add(Circle::new(GLOW_RADIUS))),
+                    MeshMaterial2d(materials.add(Color::srgba(1.0, 0.9, 0.6, 0.25))),
+                    Transform::from_xyz(0.0, 0.0, -0.1),
+                    PlayerGlow,
+                ),
+                player_icon(&asset_server, &save.profile),
+            ],
+        )],
+    ));
+}
+
+// 角色参与模拟的部分, 显示部分由 game_init 作为子实体添加
+fn role_bundle(start: &Checkpoint) -> impl Bundle {
+    (
+        RigidBody::KinematicPositionBased,
         Collider::ball(BALL_SIZE),
-        GravityScale(0.0),
-        Restitution::coefficient(0.0),
-        Friction::coefficient(0.0),
+        KinematicCharacterController {
+            offset: CharacterLength::Absolute(1.0),
+            up: Vec2::Y,
+            slide: true,
+            autostep: None,
+            max_slope_climb_angle: GROUND_NORMAL_MIN_Y.acos(),
+            min_slope_slide_angle: GROUND_NORMAL_MIN_Y.acos(),
+            snap_to_ground: Some(CharacterLength::Absolute(GROUND_SNAP)),
+            filter_flags: QueryFilterFlags::EXCLUDE_SENSORS,
+            ..default()
+        },
+        // 运动学刚体默认不和静态刚体产生碰撞事件, 传感器道具需要打开
+        ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
         ActiveEvents::COLLISION_EVENTS,
-        //Sprite::from_image(asset_server.load("block.png")),
-        RoleState::Air(999),
-        RoleSpeed(ROLE_SPEED, 0.0),
-        Transform::from_xyz(-100.0, 200.0, 0.0),
+        start.state,
+        RoleSpeed(start.speed.x, start.speed.y),
+        SimPosition {
+            prev: start.pos,
+            cur: start.pos,
+        },
+        Transform::from_translation(start.pos.extend(0.0)),
+    )
+}
+
+fn spawn_checkpoint_marker(cmd: &mut Commands, pos: Vec2) {
+    cmd.spawn((
+        Sprite::from_color(Color::srgba(0.3, 0.9, 0.4, 0.8), Vec2::splat(16.0)),
+        Transform::from_translation(pos.extend(0.5))
+            .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)),
+        LevelDecor,
     ));
 }
 
+// 练习模式下在角色当前位置放置检查点
+pub fn place_checkpoint(
+    mut cmd: Commands,
+    mut practice: ResMut<Practice>,
+    role: Single<(&SimPosition, &RoleSpeed, &RoleState)>,
+    camera_transform: Single<&Transform, (With<Camera>, Without<RoleSpeed>)>,
+    run_stats: Res<RunStats>,
+) {
+    if !practice.enabled {
+        return;
+    }
+    let (sim_pos, speed, state) = role.into_inner();
+    info!("place checkpoint at {}", sim_pos.cur);
+    practice.checkpoints.push(Checkpoint {
+        pos: sim_pos.cur,
+        speed: Vec2::new(speed.0, speed.1),
+        state: *state,
+        camera_y: camera_transform.translation.y,
+        time: run_stats.time,
+    });
+    spawn_checkpoint_marker(&mut cmd, sim_pos.cur);
+}
+
 pub fn gravity(role_sv: Single<(&mut RoleSpeed, &RoleState)>, time: Res<Time>) {
     let (mut role_speed, role_state) = role_sv.into_inner();
     if let RoleState::Air(_) = *role_state {
@@ -227,18 +397,213 @@ pub fn gravity(role_sv: Single<(&mut RoleSpeed, &RoleState)>, time: Res<Time>) {
     }
 }
 
+fn boom(
+    cmd: &mut Commands,
+    role: Entity,
+    camera_pos: Vec3,
+    respawn_delay: f32,
+    game_events: &mut MessageWriter<GameEvent>,
+    nxt_state: &mut ResMut<NextState<GameState>>,
+) {
+    info!("boom!");
+    game_events.write(GameEvent::Death);
+    cmd.entity(role)
+        .insert((ColliderDisabled, Visibility::Hidden));
+    cmd.insert_resource(DeathSequence {
+        timer: Timer::from_seconds(respawn_delay.max(0.0), TimerMode::Once),
+        shake_origin: camera_pos,
+    });
+    nxt_state.set(GameState::Dead);
+}
+
+// 销毁关卡内的全部实体, 死亡重开和返回主菜单共用
+pub fn despawn_level(
+    cmd: &mut Commands,
+    level_entities: &Query<Entity, Or<(With<MapItem>, With<RoleSpeed>, With<LevelDecor>)>>,
+    lv_idx_entity_paires: &mut IdxEntityPair,
+) {
+    for entity in level_entities {
+        cmd.entity(entity).despawn();
+    }
+    lv_idx_entity_paires.pairs.clear();
+}
+
+pub fn dead_update(
+    mut cmd: Commands,
+    time: Res<Time>,
+    mut death: ResMut<DeathSequence>,
+    death_config: Res<DeathConfig>,
+    mut camera_transform: Single<
+        &mut Transform,
+        (With<Camera>, Without<RoleSpeed>, Without<MapItem>),
+    >,
+    level_entities: Query<Entity, Or<(With<MapItem>, With<RoleSpeed>, With<LevelDecor>)>>,
+    mut lv_idx_entity_paires: ResMut<IdxEntityPair>,
+    mut stats: ResMut<SessionStats>,
+    mut nxt_state: ResMut<NextState<GameState>>,
+) {
+    death.timer.tick(time.delta());
+    let strength = death_config.shake_strength * death.timer.fraction_remaining();
+    let shake = Vec2::new(rand::random::<f32>(), rand::random::<f32>()) * 2.0 - Vec2::ONE;
+    camera_transform.translation = death.shake_origin + (shake * strength).extend(0.0);
+
+    if death.timer.is_finished() {
+        info!("respawn");
+        camera_transform.translation = death.shake_origin;
+        despawn_level(&mut cmd, &level_entities, &mut lv_idx_entity_paires);
+        cmd.remove_resource::<DeathSequence>();
+        stats.respawn = true;
+        nxt_state.set(GameState::InitLevel);
+    }
+}
+
+// 游戏中或暂停时按快捷键重开本关
+pub fn request_restart(mut restarts: MessageWriter<RestartLevel>) {
+    restarts.write(RestartLevel);
+}
+
+// 重开本关, 练习模式的检查点一并清掉
+pub fn restart_level(
+    mut cmd: Commands,
+    mut restarts: MessageReader<RestartLevel>,
+    level_entities: Query<Entity, Or<(With<MapItem>, With<RoleSpeed>, With<LevelDecor>)>>,
+    mut lv_idx_entity_paires: ResMut<IdxEntityPair>,
+    mut stats: ResMut<SessionStats>,
+    mut practice: ResMut<Practice>,
+    mut nxt_state: ResMut<NextState<GameState>>,
+) {
+    if restarts.read().count() == 0 {
+        return;
+    }
+    info!("restart level");
+    practice.checkpoints.clear();
+    despawn_level(&mut cmd, &level_entities, &mut lv_idx_entity_paires);
+    stats.respawn = true;
+    nxt_state.set(GameState::InitLevel);
+}
+
+// 倒计时期间物理不步进, 角色和道具不会提前产生碰撞
+pub fn pause_physics(mut config: Query<&mut RapierConfiguration>) {
+    for mut config in config.iter_mut() {
+        config.physics_pipeline_active = false;
+    }
+}
+
+pub fn resume_physics(mut config: Query<&mut RapierConfiguration>) {
+    for mut config in config.iter_mut() {
+        config.physics_pipeline_active = true;
+    }
+}
+
+// 处理角色控制器上一步移动中碰到的实体道具, 决定落地、离地和死亡
+pub fn character_collisions(
+    mut cmd: Commands,
+    role: Single<(
+        Entity,
+        &mut RoleSpeed,
+        &mut RoleState,
+        Option<&KinematicCharacterControllerOutput>,
+    )>,
+    map_item_entities: Query<(Entity, &MapItem)>,
+    camera_transform: Single<&Transform, (With<Camera>, Without<RoleSpeed>)>,
+    settings: Res<Settings>,
+    mut game_events: MessageWriter<GameEvent>,
+    mut nxt_state: ResMut<NextState<GameState>>,
+) {
+    let (role_entity, mut role_speed, mut role_state, output) = role.into_inner();
+    let Some(output) = output else {
+        return;
+    };
+
+    let mut ground: Option<Vec2> = None;
+    for collision in output.collisions.iter() {
+        let Ok((_, map_item)) = map_item_entities.get(collision.entity) else {
+            continue;
+        };
+        // normal1 是角色形状上的法线, 取反即接触面指向角色的法线
+        let normal = collision
+            .hit
+            .details
+            .as_ref()
+            .map(|details| -details.normal1)
+            .unwrap_or(Vec2::Y);
+        match map_item {
+            MapItem::Obstacle => {
+                boom(
+                    &mut cmd,
+                    role_entity,
+                    camera_transform.translation,
+                    settings.respawn_delay,
+                    &mut game_events,
+                    &mut nxt_state,
+                );
+                return;
+            }
+            MapItem::Normal | MapItem::Slope => {
+                if normal.y > GROUND_NORMAL_MIN_Y {
+                    if ground.is_none_or(|g| normal.y > g.y) {
+                        ground = Some(normal);
+                    }
+                } else if normal.y < -GROUND_NORMAL_MIN_Y {
+                    // 顶到天花板
+                    role_speed.1 = role_speed.1.min(0.0);
+                } else if let MapItem::Slope = map_item {
+                    // 撞上太陡的坡面和撞上障碍一样
+                    boom(
+                        &mut cmd,
+                        role_entity,
+                        camera_transform.translation,
+                        settings.respawn_delay,
+                        &mut game_events,
+                        &mut nxt_state,
+                    );
+                    return;
+                }
+            }
+            MapItem::Pass => {
+                info!("collide pass");
+                game_events.write(GameEvent::LevelComplete);
+                nxt_state.set(GameState::LevelComplete);
+                return;
+            }
+            MapItem::DoubleJump | MapItem::FlyBegin | MapItem::FlyEnd | MapItem::Coin => (),
+        }
+    }
+
+    match *role_state {
+        RoleState::Air(_) => {
+            if role_speed.1 <= 0.0
+                && let Some(normal) = ground.or(output.grounded.then_some(Vec2::Y))
+            {
+                info!("collide floor, normal {}", normal);
+                *role_state = RoleState::Normal(normal);
+                role_speed.1 = 0.0;
+                game_events.write(GameEvent::Land);
+            }
+        }
+        RoleState::Normal(_) => {
+            if let Some(normal) = ground {
+                *role_state = RoleState::Normal(normal);
+            } else if !output.grounded {
+                // 坡顶离开时保留沿坡的竖直速度, 自然飞出
+                *role_state = RoleState::Air(0);
+            }
+        }
+    }
+}
+
 /* A system that displays the events. */
 pub fn collide_events(
     mut cmd: Commands,
     mut collision_events: MessageReader<CollisionEvent>,
-    role_sv: Single<(&mut RoleSpeed, &mut RoleState)>,
+    mut role_state: Single<&mut RoleState>,
     role_entity: Single<Entity, With<RoleState>>,
     map_item_entities: Query<(Entity, &MapItem)>,
-    mut nxt_state: ResMut<NextState<GameState>>,
     state: Res<State<GameState>>,
+    mut run_stats: ResMut<RunStats>,
     mut lv_idx_entity_paires: ResMut<IdxEntityPair>,
+    mut game_events: MessageWriter<GameEvent>,
 ) {
-    let (mut role_speed, mut role_state) = role_sv.into_inner();
     for collision_event in collision_events.read() {
         if let CollisionEvent::Started(entity1, entity2, _) = collision_event {
             info!("collide: {}, {}", entity1, entity2);
@@ -250,39 +615,40 @@ pub fn collide_events(
                 for (entity, map_item) in map_item_entities.iter() {
                     if entity == *other_entity {
                         match map_item {
-                            MapItem::Obstacle => {
-                                //nxt_state.set(GameState::Paused);
-                                info!("boom!");
-                                for (dee, _) in map_item_entities.iter() {
-                                    cmd.entity(dee).despawn();
-                                }
-                                lv_idx_entity_paires.pairs.clear();
-                                cmd.entity(*role_entity).despawn();
-                                nxt_state.set(GameState::InitLevel);
-                                return;
-                            }
                             MapItem::DoubleJump => {
-                                if let RoleState::Air(_) = *role_state {
-                                    *role_state = RoleState::Air(1);
+                                if let RoleState::Air(_) = **role_state {
+                                    **role_state = RoleState::Air(1);
                                 }
                             }
-                            MapItem::Normal => {
-                                info!("collide floor");
-                                *role_state = RoleState::Normal;
-                                role_speed.1 = 0.0;
-                            }
                             MapItem::FlyBegin => {
                                 info!("collide fly begin");
-                                *role_state = RoleState::Air(999);
+                                game_events.write(GameEvent::PortalEntered);
+                                **role_state = RoleState::Air(999);
                             }
                             MapItem::FlyEnd => {
                                 info!("collide fly end");
-                                *role_state = RoleState::Air(0);
+                                game_events.write(GameEvent::PortalEntered);
+                                **role_state = RoleState::Air(0);
                             }
-                            MapItem::Pass => {
-                                info!("collide pass");
-                                nxt_state.set(GameState::Paused);
+                            MapItem::Coin => {
+                                let idx = lv_idx_entity_paires
+                                    .pairs
+                                    .iter()
+                                    .find_map(|(idx, (e, _))| (*e == entity).then_some(*idx));
+                                if let Some(idx) = idx {
+                                    info!("collect coin {}", idx);
+                                    lv_idx_entity_paires.pairs.remove(&idx);
+                                    run_stats.collected.insert(idx);
+                                    run_stats.coins += 1;
+                                    game_events.write(GameEvent::CoinCollected);
+                                    cmd.entity(entity).despawn();
+                                }
                             }
+                            // 实体道具由 character_collisions 处理
+                            MapItem::Obstacle
+                            | MapItem::Normal
+                            | MapItem::Slope
+                            | MapItem::Pass => (),
                         }
                     }
                 }
@@ -299,17 +665,20 @@ pub fn collide_events(
                     for (entity, map_item) in map_item_entities.iter() {
                         if entity == *other_entity {
                             match map_item {
-                                MapItem::Obstacle
-                                | MapItem::Normal
-                                | MapItem::DoubleJump
-                                | MapItem::Pass
-                                | MapItem::FlyEnd => {
-                                    *role_state = RoleState::Air(0);
+                                MapItem::DoubleJump | MapItem::FlyEnd => {
+                                    if let RoleState::Air(_) = **role_state {
+                                        **role_state = RoleState::Air(0);
+                                    }
                                 }
                                 MapItem::FlyBegin => {
                                     info!("collide fly begin");
                                     //*role_state = RoleState::Air(999);
                                 }
+                                MapItem::Obstacle
+                                | MapItem::Normal
+                                | MapItem::Slope
+                                | MapItem::Pass
+                                | MapItem::Coin => (),
                             }
                         }
                     }
@@ -321,8 +690,40 @@ pub fn collide_events(
     }
 }
 
-pub fn jump(role_sv: Single<(&mut RoleSpeed, &mut RoleState)>) {
+// 站在地面上时竖直速度沿接触面切线, 平地上为 0
+pub fn follow_ground(role_sv: Single<(&mut RoleSpeed, &RoleState)>) {
+    let (mut role_speed, role_state) = role_sv.into_inner();
+    if let RoleState::Normal(normal) = *role_state {
+        role_speed.1 = -role_speed.0 * normal.x / normal.y;
+    }
+}
+
+pub fn buffer_jump(mut jump_input: ResMut<JumpInput>) {
+    jump_input.0 = true;
+}
+
+// 死亡、暂停或通关那一帧按下的跳跃还没被固定步长消费, 离开时丢弃
+pub fn clear_jump_input(mut jump_input: ResMut<JumpInput>) {
+    jump_input.0 = false;
+}
+
+pub fn jump(
+    role_sv: Single<(&mut RoleSpeed, &mut RoleState)>,
+    mut jump_input: ResMut<JumpInput>,
+    mut run_stats: ResMut<RunStats>,
+    mut game_events: MessageWriter<GameEvent>,
+) {
+    if !jump_input.0 {
+        return;
+    }
+    jump_input.0 = false;
     let (mut role_speed, mut role_state) = role_sv.into_inner();
+    // 碰到跳跃球后只剩一次空中跳跃
+    let event = if let RoleState::Air(1) = *role_state {
+        GameEvent::OrbUsed
+    } else {
+        GameEvent::Jump
+    };
     if let RoleState::Air(jn) = *role_state {
         info!("jump times {}", jn);
         if jn == 0 {
@@ -334,20 +735,88 @@ pub fn jump(role_sv: Single<(&mut RoleSpeed, &mut RoleState)>) {
         *role_state = RoleState::Air(0);
     }
     role_speed.1 = JUMP_SPEED;
+    run_stats.jumps += 1;
+    game_events.write(event);
 }
 
-pub fn role_move(
-    role: Single<(&mut Transform, &RoleSpeed)>,
+pub fn run_timer(
+    mut run_stats: ResMut<RunStats>,
+    mut music_time: ResMut<MusicTime>,
     time: Res<Time>,
+) {
+    run_stats.time += time.delta_secs();
+    music_time.0 += time.delta_secs();
+}
+
+// 在 FixedUpdate 中运行, time.delta_secs() 为固定步长
+// 只给出期望位移, 实际移动由角色控制器在物理步中完成
+pub fn role_move(role: Single<(&mut KinematicCharacterController, &RoleSpeed)>, time: Res<Time>) {
+    let (mut controller, speed) = role.into_inner();
+    controller.translation = Some(Vec2::new(speed.0, speed.1) * time.delta_secs());
+}
+
+// 物理写回之后记录本步长角色位置
+pub fn record_sim_position(role: Single<(&Transform, &mut SimPosition)>) {
+    let (role_transform, mut sim_pos) = role.into_inner();
+    sim_pos.prev = sim_pos.cur;
+    sim_pos.cur = role_transform.translation.truncate();
+}
+
+// 固定步长里的角色模拟, main 和确定性测试共用同一份调度
+pub fn simulation_systems() -> ScheduleConfigs<ScheduleSystem> {
+    (
+        collide_events.run_if(in_state(GameState::InitLevel).or(in_state(GameState::Playing))),
+        (
+            character_collisions,
+            jump,
+            gravity,
+            follow_ground,
+            role_move,
+            dynamic_map_item,
+            run_timer,
+        )
+            .chain()
+            .run_if(in_state(GameState::Playing)),
+    )
+        .chain()
+        .into_configs()
+}
+
+// 镜头按插值后的角色位置跟随, 渲染平滑且不影响模拟结果
+// 竖直方向角色离开死区后平滑跟随, 并限制在关卡设定的范围内
+pub fn camera_follow(
+    sim_pos: Single<&SimPosition>,
+    fixed_time: Res<Time<Fixed>>,
+    time: Res<Time>,
+    level_data: Res<LevelData>,
     mut camera_transform: Single<
         &mut Transform,
         (With<Camera>, Without<RoleSpeed>, Without<MapItem>),
     >,
 ) {
-    let (mut role_transform, speed) = role.into_inner();
-    role_transform.translation.x += speed.0 * time.delta_secs();
-    role_transform.translation.y += speed.1 * time.delta_secs();
-    camera_transform.translation.x += speed.0 * time.delta_secs();
+    let pos = sim_pos.interpolated(fixed_time.overstep_fraction());
+    camera_transform.translation.x = pos.x + CAMERA_OFFSET_X;
+
+    let cam_y = camera_transform.translation.y;
+    let target_y = if pos.y > cam_y + CAMERA_DEAD_ZONE_Y {
+        pos.y - CAMERA_DEAD_ZONE_Y
+    } else if pos.y < cam_y - CAMERA_DEAD_ZONE_Y {
+        pos.y + CAMERA_DEAD_ZONE_Y
+    } else {
+        cam_y
+    };
+    let t = 1.0 - (-CAMERA_SMOOTHING * time.delta_secs()).exp();
+    camera_transform.translation.y = level_data.clamp_camera_y(cam_y + (target_y - cam_y) * t);
+}
+
+// 角色的 Transform 停在最近一个固定步长的位置, 显示部分偏移到插值位置, 和镜头一致
+pub fn interpolate_role_visual(
+    sim_pos: Single<&SimPosition>,
+    fixed_time: Res<Time<Fixed>>,
+    mut visual: Single<&mut Transform, With<RoleVisual>>,
+) {
+    let offset = sim_pos.interpolated(fixed_time.overstep_fraction()) - sim_pos.cur;
+    visual.translation = offset.extend(0.0);
 }
 
 pub fn game_pause_play(state: Res<State<GameState>>, mut nxt_state: ResMut<NextState<GameState>>) {
@@ -365,6 +834,116 @@ pub fn game_pause_play(state: Res<State<GameState>>, mut nxt_state: ResMut<NextS
 }
 
 pub fn setup(mut cmd: Commands) {
-    cmd.spawn(Camera2d::default());
+    cmd.spawn((
+        Camera2d,
+        Projection::Orthographic(OrthographicProjection {
+            scaling_mode: ScalingMode::FixedVertical {
+                viewport_height: VIRTUAL_HEIGHT,
+            },
+            ..OrthographicProjection::default_2d()
+        }),
+    ));
     //spawn_main_menu(cmd, lvs);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::state::app::StatesPlugin;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    // 比较的固定步长数(120Hz 下 5 秒)和按下跳跃的步长
+    const TRACE_TICKS: usize = 600;
+    const JUMP_TICKS: [u32; 5] = [40, 180, 181, 300, 420];
+
+    #[derive(Resource, Default)]
+    struct Trace(Vec<Vec2>);
+
+    // 按步长而不是按帧给出输入, 同样的输入序列在任何帧率下都一样
+    fn scripted_jump(mut tick: Local<u32>, mut jump_input: ResMut<JumpInput>) {
+        if JUMP_TICKS.contains(&*tick) {
+            jump_input.0 = true;
+        }
+        *tick += 1;
+    }
+
+    fn record_trace(sim_pos: Single<&SimPosition>, mut trace: ResMut<Trace>) {
+        trace.0.push(sim_pos.cur);
+    }
+
+    // 地面上有一段坡, 角色会落地、跳跃、冲上坡顶飞出
+    fn test_level() -> LevelData {
+        LevelData {
+            data: vec![
+                MapItemData::Floor(Vec4::new(1400.0, -20.0, 1600.0, 20.0)),
+                MapItemData::Slope(vec![
+                    Vec2::new(600.0, 0.0),
+                    Vec2::new(900.0, 0.0),
+                    Vec2::new(900.0, 100.0),
+                ]),
+            ],
+            camera_max_y: f32::MAX,
+            ..default()
+        }
+    }
+
+    // 以给定的渲染帧间隔运行, 返回每个固定步长结束时角色的模拟位置
+    fn sim_trace(frame: Duration) -> Vec<Vec2> {
+        let settings = Settings::default();
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            StatesPlugin,
+            AssetPlugin::default(),
+        ))
+        .init_asset::<Mesh>()
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0).in_fixed_schedule())
+        .insert_resource(TimeUpdateStrategy::ManualDuration(frame))
+        .insert_resource(Time::<Fixed>::from_hz(settings.tick_hz()))
+        .insert_resource(settings.timestep_mode())
+        .insert_resource(settings)
+        .insert_resource(test_level())
+        .init_resource::<RunStats>()
+        .init_resource::<IdxEntityPair>()
+        .init_resource::<JumpInput>()
+        .init_resource::<MusicTime>()
+        .init_resource::<Trace>()
+        .add_message::<GameEvent>()
+        .insert_state(GameState::Playing)
+        .add_systems(FixedPreUpdate, scripted_jump)
+        .add_systems(FixedUpdate, simulation_systems())
+        .add_systems(FixedLast, (record_sim_position, record_trace).chain());
+
+        app.world_mut().spawn((Camera2d, Transform::default()));
+        app.world_mut().spawn(role_bundle(&Checkpoint {
+            pos: ROLE_START,
+            speed: Vec2::new(ROLE_SPEED, 0.0),
+            state: RoleState::Air(999),
+            camera_y: 0.0,
+            time: 0.0,
+        }));
+
+        for _ in 0..10_000 {
+            if app.world().resource::<Trace>().0.len() >= TRACE_TICKS {
+                break;
+            }
+            app.update();
+        }
+        let trace = app.world_mut().remove_resource::<Trace>().unwrap().0;
+        assert!(
+            trace.len() >= TRACE_TICKS,
+            "role stopped after {} ticks",
+            trace.len()
+        );
+        trace[..TRACE_TICKS].to_vec()
+    }
+
+    #[test]
+    fn same_inputs_same_trace_at_any_frame_rate() {
+        let slow = sim_trace(Duration::from_secs_f64(1.0 / 30.0));
+        let fast = sim_trace(Duration::from_secs_f64(1.0 / 144.0));
+        assert_eq!(slow, fast);
+    }
+}
@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+
+use std::f32::consts::FRAC_PI_2;
+use std::fs::read_dir;
+
+use crate::save::Profile;
+use crate::theme::parse_color;
+use crate::types::*;
+
+// 每个图标由 <名字>_primary.png 和 <名字>_secondary.png 两层组成, 白色部分按玩家颜色染色
+pub const ICON_PATH: &str = "assets/icons";
+pub const DEFAULT_ICON: &str = "cube";
+
+// assets/icons 下的图标, 按名字排序
+pub fn icon_names() -> Vec<String> {
+    let mut names = read_dir(ICON_PATH)
+        .map(|dirs| {
+            dirs.filter_map(|e| {
+                let path = e.ok()?.path();
+                let name = path.file_name()?.to_str()?.strip_suffix("_primary.png")?;
+                Some(name.to_owned())
+            })
+            .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+// 主色层和副色层的贴图
+pub fn icon_images(asset_server: &AssetServer, icon: &str) -> (Handle<Image>, Handle<Image>) {
+    (
+        asset_server.load(format!("icons/{}_primary.png", icon)),
+        asset_server.load(format!("icons/{}_secondary.png", icon)),
+    )
+}
+
+// 角色的图标实体, 作为角色的子实体, 旋转不影响碰撞体
+pub fn player_icon(asset_server: &AssetServer, profile: &Profile) -> impl Bundle {
+    let (primary, secondary) = icon_images(asset_server, &profile.icon);
+    let sprite = |image: Handle<Image>, color: &str| Sprite {
+        image,
+        color: parse_color(Some(color)),
+        custom_size: Some(Vec2::splat(ICON_SIZE)),
+        ..default()
+    };
+    (
+        sprite(primary, &profile.primary),
+        Transform::from_xyz(0.0, 0.0, 1.0),
+        PlayerIcon,
+        children![(
+            sprite(secondary, &profile.secondary),
+            Transform::from_xyz(0.0, 0.0, 0.01),
+        )],
+    )
+}
+
+// 空中顺时针旋转, 落地后转到最近的 90 度, 在坡上按坡面对齐
+pub fn rotate_icon(
+    time: Res<Time>,
+    role_state: Single<&RoleState>,
+    mut icon: Single<&mut Transform, With<PlayerIcon>>,
+) {
+    let angle = icon.rotation.to_euler(EulerRot::XYZ).2;
+    let angle = match **role_state {
+        RoleState::Air(_) => angle - ICON_SPIN_SPEED * time.delta_secs(),
+        RoleState::Normal(normal) => {
+            let base = normal.to_angle() - FRAC_PI_2;
+            let target = base + ((angle - base) / FRAC_PI_2).round() * FRAC_PI_2;
+            let t = 1.0 - (-ICON_SNAP_SPEED * time.delta_secs()).exp();
+            angle + (target - angle) * t
+        }
+    };
+    icon.rotation = Quat::from_rotation_z(angle);
+}
@@ -0,0 +1,293 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::settings::Settings;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Action {
+    Jump,
+    Pause,
+    Restart,
+    Confirm,
+    PlaceCheckpoint,
+    MenuUp,
+    MenuDown,
+    MenuLeft,
+    MenuRight,
+    ToggleDebug,
+    ToggleHitboxes,
+}
+
+impl Action {
+    pub const ALL: [Action; 11] = [
+        Action::Jump,
+        Action::Pause,
+        Action::Restart,
+        Action::Confirm,
+        Action::PlaceCheckpoint,
+        Action::MenuUp,
+        Action::MenuDown,
+        Action::MenuLeft,
+        Action::MenuRight,
+        Action::ToggleDebug,
+        Action::ToggleHitboxes,
+    ];
+
+    // 界面上显示的名字在字符串表里的 key
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Jump => "action_jump",
+            Action::Pause => "action_pause",
+            Action::Restart => "action_restart",
+            Action::Confirm => "action_confirm",
+            Action::PlaceCheckpoint => "action_checkpoint",
+            Action::MenuUp => "action_menu_up",
+            Action::MenuDown => "action_menu_down",
+            Action::MenuLeft => "action_menu_left",
+            Action::MenuRight => "action_menu_right",
+            Action::ToggleDebug => "action_debug_overlay",
+            Action::ToggleHitboxes => "action_hitboxes",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InputBinding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+// 配置文件里按键用名字保存
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Space", KeyCode::Space),
+    ("Enter", KeyCode::Enter),
+    ("Escape", KeyCode::Escape),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("ShiftLeft", KeyCode::ShiftLeft),
+    ("ShiftRight", KeyCode::ShiftRight),
+    ("ControlLeft", KeyCode::ControlLeft),
+    ("ControlRight", KeyCode::ControlRight),
+    ("ArrowUp", KeyCode::ArrowUp),
+    ("ArrowDown", KeyCode::ArrowDown),
+    ("ArrowLeft", KeyCode::ArrowLeft),
+    ("ArrowRight", KeyCode::ArrowRight),
+    ("A", KeyCode::KeyA),
+    ("B", KeyCode::KeyB),
+    ("C", KeyCode::KeyC),
+    ("D", KeyCode::KeyD),
+    ("E", KeyCode::KeyE),
+    ("F", KeyCode::KeyF),
+    ("G", KeyCode::KeyG),
+    ("H", KeyCode::KeyH),
+    ("I", KeyCode::KeyI),
+    ("J", KeyCode::KeyJ),
+    ("K", KeyCode::KeyK),
+    ("L", KeyCode::KeyL),
+    ("M", KeyCode::KeyM),
+    ("N", KeyCode::KeyN),
+    ("O", KeyCode::KeyO),
+    ("P", KeyCode::KeyP),
+    ("Q", KeyCode::KeyQ),
+    ("R", KeyCode::KeyR),
+    ("S", KeyCode::KeyS),
+    ("T", KeyCode::KeyT),
+    ("U", KeyCode::KeyU),
+    ("V", KeyCode::KeyV),
+    ("W", KeyCode::KeyW),
+    ("X", KeyCode::KeyX),
+    ("Y", KeyCode::KeyY),
+    ("Z", KeyCode::KeyZ),
+    ("0", KeyCode::Digit0),
+    ("1", KeyCode::Digit1),
+    ("2", KeyCode::Digit2),
+    ("3", KeyCode::Digit3),
+    ("4", KeyCode::Digit4),
+    ("5", KeyCode::Digit5),
+    ("6", KeyCode::Digit6),
+    ("7", KeyCode::Digit7),
+    ("8", KeyCode::Digit8),
+    ("9", KeyCode::Digit9),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+];
+
+const MOUSE_NAMES: &[(&str, MouseButton)] = &[
+    ("Left", MouseButton::Left),
+    ("Right", MouseButton::Right),
+    ("Middle", MouseButton::Middle),
+];
+
+const PAD_NAMES: &[(&str, GamepadButton)] = &[
+    ("South", GamepadButton::South),
+    ("East", GamepadButton::East),
+    ("North", GamepadButton::North),
+    ("West", GamepadButton::West),
+    ("LeftTrigger", GamepadButton::LeftTrigger),
+    ("RightTrigger", GamepadButton::RightTrigger),
+    ("LeftTrigger2", GamepadButton::LeftTrigger2),
+    ("RightTrigger2", GamepadButton::RightTrigger2),
+    ("Select", GamepadButton::Select),
+    ("Start", GamepadButton::Start),
+    ("DPadUp", GamepadButton::DPadUp),
+    ("DPadDown", GamepadButton::DPadDown),
+    ("DPadLeft", GamepadButton::DPadLeft),
+    ("DPadRight", GamepadButton::DPadRight),
+];
+
+fn lookup<T: Copy + PartialEq>(table: &[(&'static str, T)], name: &str) -> Option<T> {
+    table.iter().find_map(|(n, v)| (*n == name).then_some(*v))
+}
+
+fn reverse_lookup<T: Copy + PartialEq>(table: &[(&'static str, T)], v: T) -> Option<&'static str> {
+    table.iter().find_map(|(n, t)| (*t == v).then_some(*n))
+}
+
+impl InputBinding {
+    // "Key:Space", "Mouse:Left", "Pad:South"
+    pub fn parse(s: &str) -> Option<Self> {
+        let (device, name) = s.split_once(':')?;
+        match device {
+            "Key" => lookup(KEY_NAMES, name).map(InputBinding::Key),
+            "Mouse" => lookup(MOUSE_NAMES, name).map(InputBinding::Mouse),
+            "Pad" => lookup(PAD_NAMES, name).map(InputBinding::Gamepad),
+            _ => None,
+        }
+    }
+
+    pub fn to_config(self) -> Option<String> {
+        Some(match self {
+            InputBinding::Key(key) => format!("Key:{}", reverse_lookup(KEY_NAMES, key)?),
+            InputBinding::Mouse(button) => {
+                format!("Mouse:{}", reverse_lookup(MOUSE_NAMES, button)?)
+            }
+            InputBinding::Gamepad(button) => format!("Pad:{}", reverse_lookup(PAD_NAMES, button)?),
+        })
+    }
+
+    pub fn same_device(self, other: InputBinding) -> bool {
+        matches!(
+            (self, other),
+            (InputBinding::Key(_), InputBinding::Key(_))
+                | (InputBinding::Mouse(_), InputBinding::Mouse(_))
+                | (InputBinding::Gamepad(_), InputBinding::Gamepad(_))
+        )
+    }
+}
+
+pub fn default_bindings() -> BTreeMap<Action, Vec<String>> {
+    let bindings: [(Action, &[&str]); 11] = [
+        (
+            Action::Jump,
+            &["Key:Space", "Key:ArrowUp", "Mouse:Left", "Pad:South"],
+        ),
+        (Action::Pause, &["Key:Escape", "Pad:Start"]),
+        (Action::Restart, &["Key:R", "Pad:Select"]),
+        (Action::Confirm, &["Key:Enter", "Pad:East"]),
+        (Action::PlaceCheckpoint, &["Key:Z", "Pad:West"]),
+        (Action::MenuUp, &["Key:ArrowUp", "Key:W", "Pad:DPadUp"]),
+        (
+            Action::MenuDown,
+            &["Key:ArrowDown", "Key:S", "Pad:DPadDown"],
+        ),
+        (
+            Action::MenuLeft,
+            &["Key:ArrowLeft", "Key:A", "Pad:DPadLeft"],
+        ),
+        (
+            Action::MenuRight,
+            &["Key:ArrowRight", "Key:D", "Pad:DPadRight"],
+        ),
+        (Action::ToggleDebug, &["Key:F3"]),
+        (Action::ToggleHitboxes, &["Key:F4"]),
+    ];
+    bindings
+        .into_iter()
+        .map(|(action, b)| (action, b.iter().map(|s| s.to_string()).collect()))
+        .collect()
+}
+
+// 本帧各动作的状态, 在 PreUpdate 中由键盘、鼠标和手柄汇总
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+// 鼠标在界面按钮上时点击只算点按钮, 不触发鼠标绑定的动作
+pub fn update_action_state(
+    settings: Res<Settings>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    interactions: Query<&Interaction>,
+    mut action_state: ResMut<ActionState>,
+) {
+    action_state.pressed.clear();
+    action_state.just_pressed.clear();
+    let over_ui = interactions.iter().any(|i| *i != Interaction::None);
+    for action in Action::ALL {
+        for binding in settings.bindings(action) {
+            let (pressed, just_pressed) = match binding {
+                InputBinding::Key(key) => (keys.pressed(key), keys.just_pressed(key)),
+                InputBinding::Mouse(_) if over_ui => (false, false),
+                InputBinding::Mouse(button) => (mouse.pressed(button), mouse.just_pressed(button)),
+                InputBinding::Gamepad(button) => (
+                    gamepads.iter().any(|g| g.pressed(button)),
+                    gamepads.iter().any(|g| g.just_pressed(button)),
+                ),
+            };
+            if pressed {
+                action_state.pressed.insert(action);
+            }
+            if just_pressed {
+                action_state.just_pressed.insert(action);
+            }
+        }
+    }
+}
+
+// 本帧第一个按下的输入, 设置界面改绑时使用
+pub fn first_just_pressed(
+    keys: &ButtonInput<KeyCode>,
+    mouse: &ButtonInput<MouseButton>,
+    gamepads: &Query<&Gamepad>,
+) -> Option<InputBinding> {
+    keys.get_just_pressed()
+        .map(|key| InputBinding::Key(*key))
+        .chain(mouse.get_just_pressed().map(|b| InputBinding::Mouse(*b)))
+        .chain(
+            gamepads
+                .iter()
+                .flat_map(|g| g.get_just_pressed().copied().collect::<Vec<_>>())
+                .map(InputBinding::Gamepad),
+        )
+        .find(|binding| binding.to_config().is_some())
+}
+
+pub fn action_just_pressed(action: Action) -> impl FnMut(Res<ActionState>) -> bool + Clone {
+    move |action_state: Res<ActionState>| action_state.just_pressed(action)
+}
@@ -0,0 +1,113 @@
+use bevy::prelude::*;
+
+use std::collections::HashMap;
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+
+pub const LOCALE_PATH: &str = "assets/locales";
+pub const DEFAULT_LANGUAGE: &str = "en";
+// 覆盖中日韩字符的界面字体(assets 下的路径), 加载失败时退回 bevy 自带字体(只有拉丁字符)
+pub const UI_FONT_PATH: &str = "fonts/NotoSansSC-Regular.otf";
+
+// 当前语言的字符串表, 缺少的条目用英文补齐
+#[derive(Resource)]
+pub struct Locale {
+    pub lang: String,
+    strings: HashMap<String, String>,
+}
+
+fn read_table(lang: &str) -> HashMap<String, String> {
+    let path = Path::new(LOCALE_PATH).join(format!("{}.toml", lang));
+    let Ok(file_data) = read_to_string(&path) else {
+        warn!("missing locale file {}", path.display());
+        return HashMap::new();
+    };
+    toml::from_str(&file_data).unwrap_or_else(|e| {
+        warn!("invalid locale file {}: {}", path.display(), e);
+        HashMap::new()
+    })
+}
+
+impl Locale {
+    pub fn load(lang: &str) -> Self {
+        let mut strings = read_table(DEFAULT_LANGUAGE);
+        if lang != DEFAULT_LANGUAGE {
+            strings.extend(read_table(lang));
+        }
+        Self {
+            lang: lang.to_owned(),
+            strings,
+        }
+    }
+
+    // assets/locales 下的语言, 按文件名排序
+    pub fn languages() -> Vec<String> {
+        let mut langs = read_dir(LOCALE_PATH)
+            .map(|dirs| {
+                dirs.filter_map(|e| {
+                    let path = e.ok()?.path();
+                    if path.extension()? != "toml" {
+                        return None;
+                    }
+                    Some(path.file_stem()?.to_str()?.to_owned())
+                })
+                .collect::<Vec<String>>()
+            })
+            .unwrap_or_default();
+        langs.sort();
+        langs
+    }
+
+    // 没有翻译时直接显示 key, 方便发现遗漏
+    pub fn get(&self, key: &str) -> String {
+        self.strings
+            .get(key)
+            .cloned()
+            .unwrap_or_else(|| key.to_owned())
+    }
+
+    // 依次替换字符串里的 {}
+    pub fn format(&self, key: &str, args: &[String]) -> String {
+        let mut text = self.get(key);
+        for arg in args {
+            if let Some(pos) = text.find("{}") {
+                text.replace_range(pos..pos + 2, arg);
+            }
+        }
+        text
+    }
+}
+
+// 界面上的固定文字, 切换语言时按 key 重新取
+#[derive(Component)]
+pub struct Localized(pub &'static str);
+
+pub fn localize_texts(locale: Res<Locale>, mut texts: Query<(&mut Text, &Localized)>) {
+    for (mut text, localized) in texts.iter_mut() {
+        text.0 = locale.get(localized.0);
+    }
+}
+
+// 界面字体的句柄, 加载完成后替换默认字体
+#[derive(Resource)]
+pub struct UiFont(pub Handle<Font>);
+
+pub fn load_ui_font(mut cmd: Commands, asset_server: Res<AssetServer>) {
+    cmd.insert_resource(UiFont(asset_server.load(UI_FONT_PATH)));
+}
+
+// 用支持中文的字体替换默认字体, 所有没指定字体的文字都会用上它
+pub fn apply_ui_font(
+    mut font_events: MessageReader<AssetEvent<Font>>,
+    ui_font: Res<UiFont>,
+    mut fonts: ResMut<Assets<Font>>,
+) {
+    for event in font_events.read() {
+        if event.is_loaded_with_dependencies(&ui_font.0)
+            && let Some(font) = fonts.get(&ui_font.0).cloned()
+        {
+            info!("use ui font {}", UI_FONT_PATH);
+            let _ = fonts.insert(AssetId::default(), font);
+        }
+    }
+}
@@ -1,65 +1,269 @@
-use bevy::input::common_conditions::input_just_pressed;
+use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
+use bevy::input::InputSystems;
 use bevy::prelude::*;
-use bevy::window::WindowResolution;
+use bevy::ui::UiSystems;
+use bevy::window::{MonitorSelection, WindowResolution};
 use bevy_rapier2d::prelude::*;
 
+mod audio;
+mod background;
+mod beat;
+mod color;
+mod debug;
 mod game;
+mod icon;
+mod input;
+mod locale;
+mod particle;
+mod save;
+mod settings;
+mod shared;
+mod theme;
+mod thumbnail;
 mod types;
 mod ui;
 
+use audio::*;
+use background::*;
+use beat::*;
+use color::*;
+use debug::*;
 use game::*;
+use icon::*;
+use input::*;
+use locale::*;
+use particle::*;
+use save::*;
+use settings::*;
+use theme::*;
 use types::*;
 use ui::*;
 
 fn main() -> AppExit {
+    let settings = Settings::load();
+    let locale = Locale::load(&settings.language);
     App::new()
         .insert_resource(CurLevel::default())
         .insert_resource(LevelData::default())
         .insert_resource(IdxEntityPair::default())
+        .insert_resource(JumpInput::default())
+        .insert_resource(DeathConfig::default())
+        .insert_resource(SessionStats::default())
+        .insert_resource(RunStats::default())
+        .insert_resource(MusicTime::default())
+        .insert_resource(BeatPulse::default())
+        .insert_resource(ColorTriggerState::default())
+        .insert_resource(ColorChannels::default())
+        .insert_resource(ClearColor(BACKGROUND_COLOR))
+        .insert_resource(SaveData::load())
+        .insert_resource(Rebinding::default())
+        .insert_resource(MenuFocus::default())
+        .insert_resource(Practice::default())
+        .insert_resource(SettingsOrigin::default())
+        .insert_resource(ActionState::default())
+        .insert_resource(ShowHitboxes(settings.show_hitboxes))
+        .insert_resource(Time::<Fixed>::from_hz(settings.tick_hz()))
+        .insert_resource(settings.timestep_mode())
         .add_plugins(
             DefaultPlugins.set(WindowPlugin {
                 primary_window: Some(Window {
                     resolution: WindowResolution::new(WINDOW_RESOLUTION_X, WINDOW_RESOLUTION_Y)
                         .with_scale_factor_override(1.0),
-                    resizable: false,
+                    resizable: true,
+                    mode: settings
+                        .window_mode
+                        .to_window_mode(MonitorSelection::Primary),
                     ..default()
                 }),
                 ..default()
             }),
         )
-        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
-        .add_plugins(RapierDebugRenderPlugin::default())
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0).in_fixed_schedule())
+        .add_plugins(RapierDebugRenderPlugin {
+            enabled: settings.show_hitboxes,
+            ..default()
+        })
+        .add_plugins(FrameTimeDiagnosticsPlugin::default())
+        .insert_resource(settings)
+        .insert_resource(locale)
+        .insert_resource(Theme::load(DEFAULT_THEME))
+        .add_message::<GameEvent>()
+        .add_message::<RestartLevel>()
+        .init_resource::<LevelThumbnails>()
+        .init_resource::<SfxHandles>()
         .init_state::<GameState>()
-        .add_systems(Startup, setup)
+        .add_systems(Startup, (setup, load_ui_font))
+        .add_systems(
+            PreUpdate,
+            update_action_state
+                .after(InputSystems)
+                .after(UiSystems::Focus),
+        )
+        .add_systems(PreUpdate, scale_ui)
+        .add_systems(
+            PreUpdate,
+            menu_focus_navigate.after(update_action_state).run_if(
+                in_state(GameState::Main)
+                    .or(in_state(GameState::Paused))
+                    .or(in_state(GameState::LevelComplete))
+                    .or(in_state(GameState::Settings))
+                    .or(in_state(GameState::Customize)),
+            ),
+        )
+        .add_systems(PostUpdate, release_menu_press)
+        .add_systems(Update, apply_ui_font.run_if(resource_exists::<UiFont>))
         .add_systems(OnEnter(GameState::Main), main_ui)
-        .add_systems(OnEnter(GameState::InitLevel), game_init)
+        .add_systems(
+            OnEnter(GameState::InitLevel),
+            (
+                game_init,
+                background_init,
+                color_channels_init,
+                countdown_ui,
+                pause_physics,
+            ),
+        )
+        .add_systems(OnExit(GameState::InitLevel), leave_countdown)
+        .add_systems(OnEnter(GameState::Playing), (resume_physics, start_music))
+        .add_systems(OnEnter(GameState::Paused), pause_music)
+        .add_systems(OnEnter(GameState::Dead), (stop_music, reset_beat_pulse))
+        .add_systems(
+            OnEnter(GameState::Main),
+            (reset_beat_pulse, reset_color_channels),
+        )
         .add_systems(OnExit(GameState::Main), leave_main)
         .add_systems(OnEnter(GameState::Paused), pause_ui)
         .add_systems(OnExit(GameState::Paused), leave_pause)
+        .add_systems(OnEnter(GameState::Playing), hud_ui)
+        .add_systems(OnExit(GameState::Playing), (leave_hud, clear_jump_input))
+        .add_systems(OnEnter(GameState::InitLevel), save_attempt)
+        .add_systems(OnExit(GameState::Playing), save_progress)
+        .add_systems(OnEnter(GameState::LevelComplete), save_complete)
+        .add_systems(OnEnter(GameState::Settings), settings_ui)
+        .add_systems(OnExit(GameState::Settings), leave_settings)
+        .add_systems(OnEnter(GameState::Customize), customize_ui)
+        .add_systems(OnExit(GameState::Customize), leave_customize)
+        .add_systems(OnEnter(GameState::LevelComplete), level_complete_ui)
+        .add_systems(OnEnter(GameState::Main), reset_menu_focus)
+        .add_systems(OnEnter(GameState::Paused), reset_menu_focus)
+        .add_systems(OnEnter(GameState::LevelComplete), reset_menu_focus)
+        .add_systems(OnEnter(GameState::Settings), reset_menu_focus)
+        .add_systems(OnEnter(GameState::Customize), reset_menu_focus)
+        .add_systems(OnExit(GameState::LevelComplete), leave_level_complete)
+        .add_systems(FixedUpdate, simulation_systems())
+        .add_systems(
+            Update,
+            menu_focus_select
+                .after(level_grid_navigate)
+                .run_if(resource_changed::<MenuFocus>),
+        )
+        .add_systems(
+            FixedLast,
+            record_sim_position.run_if(in_state(GameState::Playing)),
+        )
         .add_systems(
             Update,
             (
-                gravity,
-                jump.run_if(input_just_pressed(KeyCode::Space)),
-                role_move,
-                dynamic_map_item,
+                buffer_jump.run_if(action_just_pressed(Action::Jump)),
+                place_checkpoint.run_if(action_just_pressed(Action::PlaceCheckpoint)),
+                camera_follow,
+                interpolate_role_visual,
+                hud_update,
+                update_beat_pulse,
+                emit_trail,
+                emit_item_particles,
+                color_triggers,
+                rotate_icon,
             )
                 .run_if(in_state(GameState::Playing)),
         )
         .add_systems(
             Update,
             (
-                game_pause_play.run_if(input_just_pressed(KeyCode::Escape)),
-                start_playing.run_if(input_just_pressed(KeyCode::Enter)),
-                start_button_action.run_if(in_state(GameState::Main)),
-                select_lv_left_button_action.run_if(in_state(GameState::Main)),
-                select_lv_right_button_action.run_if(in_state(GameState::Main)),
-                return_main_ui.run_if(in_state(GameState::Paused)),
-            ),
+                toggle_debug_overlay.run_if(action_just_pressed(Action::ToggleDebug)),
+                toggle_hitboxes.run_if(action_just_pressed(Action::ToggleHitboxes)),
+                apply_hitboxes.run_if(resource_changed::<ShowHitboxes>),
+                debug_overlay_update.run_if(any_with_component::<DebugOverlayLabel>),
+            )
+                .chain(),
         )
         .add_systems(
             Update,
-            collide_events.run_if(in_state(GameState::InitLevel).or(in_state(GameState::Playing))),
+            (
+                game_pause_play.run_if(action_just_pressed(Action::Pause)),
+                (
+                    request_restart.run_if(
+                        action_just_pressed(Action::Restart)
+                            .and(in_state(GameState::Playing).or(in_state(GameState::Paused))),
+                    ),
+                    restart_level,
+                )
+                    .chain(),
+                countdown_update.run_if(in_state(GameState::InitLevel)),
+                dead_update.run_if(in_state(GameState::Dead)),
+                (burst_particles, particle_update),
+                (update_background.after(camera_follow), fade_layers),
+                write_save.run_if(resource_changed::<SaveData>),
+                (
+                    apply_settings.run_if(resource_changed::<Settings>),
+                    localize_texts.run_if(resource_changed::<Locale>),
+                )
+                    .chain(),
+                (start_button_action, open_customize_button_action)
+                    .run_if(in_state(GameState::Main)),
+                (
+                    customize_item_action,
+                    customize_labels.run_if(resource_changed::<SaveData>),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Customize)),
+                (
+                    level_card_action,
+                    level_grid_navigate,
+                    level_grid_select.run_if(resource_changed::<CurLevel>),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Main)),
+                scroll_areas.run_if(in_state(GameState::Main).or(in_state(GameState::Settings))),
+                (
+                    apply_level_theme.run_if(resource_changed::<LevelData>),
+                    load_theme_sfx.run_if(resource_changed::<Theme>),
+                    (skin_map_items, attach_emitters),
+                )
+                    .chain(),
+                play_sfx,
+                (
+                    fade_channels,
+                    (pulse_background, pulse_glow).run_if(
+                        resource_changed::<BeatPulse>.or(resource_changed::<ColorChannels>),
+                    ),
+                    tint_items.after(skin_map_items),
+                )
+                    .chain(),
+                open_settings_button_action
+                    .run_if(in_state(GameState::Main).or(in_state(GameState::Paused))),
+                (
+                    resume_button_action,
+                    restart_button_action,
+                    practice_toggle_action,
+                )
+                    .run_if(in_state(GameState::Paused)),
+                (
+                    settings_item_action,
+                    capture_rebind,
+                    settings_labels.run_if(
+                        resource_changed::<Settings>
+                            .or(resource_changed::<Rebinding>)
+                            .or(resource_changed::<Locale>),
+                    ),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Settings)),
+                return_main_ui
+                    .run_if(in_state(GameState::Paused).or(in_state(GameState::LevelComplete))),
+                (replay_button_action, next_level_button_action)
+                    .run_if(in_state(GameState::LevelComplete)),
+            ),
         )
         .run()
 }
@@ -0,0 +1,192 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use std::f32::consts::{PI, TAU};
+
+use crate::save::{SaveData, TrailKind};
+use crate::settings::Settings;
+use crate::theme::{ParticleStyle, Theme, parse_color};
+use crate::types::*;
+
+// 粒子画在道具上面
+const PARTICLE_Z: f32 = 0.5;
+
+fn spawn_particle(
+    cmd: &mut Commands,
+    style: &ParticleStyle,
+    color: Color,
+    pos: Vec2,
+    velocity: Vec2,
+) {
+    cmd.spawn((
+        Sprite::from_color(color, Vec2::splat(style.size)),
+        Transform::from_translation(pos.extend(PARTICLE_Z)),
+        Particle {
+            velocity,
+            gravity: style.gravity,
+            life: Timer::from_seconds(style.lifetime, TimerMode::Once),
+        },
+        LevelDecor,
+    ));
+}
+
+fn random_speed(style: &ParticleStyle) -> f32 {
+    style.speed * (0.5 + rand::random::<f32>() * 0.5)
+}
+
+// 还能发射的粒子数
+fn budget(particles: &Query<(), With<Particle>>) -> usize {
+    MAX_PARTICLES.saturating_sub(particles.iter().count())
+}
+
+// 死亡爆炸向四周均匀散开, 落地火花只向上半圆溅起
+pub fn burst_particles(
+    mut cmd: Commands,
+    mut game_events: MessageReader<GameEvent>,
+    theme: Res<Theme>,
+    settings: Res<Settings>,
+    role: Query<&Transform, With<RoleSpeed>>,
+    particles: Query<(), With<Particle>>,
+) {
+    if settings.low_effects {
+        game_events.clear();
+        return;
+    }
+    let mut budget = budget(&particles);
+    for event in game_events.read() {
+        let Ok(role_transform) = role.single() else {
+            continue;
+        };
+        let role_pos = role_transform.translation.truncate();
+        let (effect, pos) = match event {
+            GameEvent::Death => (ParticleEffect::Death, role_pos),
+            GameEvent::Land => (
+                ParticleEffect::Landing,
+                role_pos - Vec2::new(0.0, BALL_SIZE),
+            ),
+            _ => continue,
+        };
+        let Some(style) = theme.manifest.particles.get(&effect) else {
+            continue;
+        };
+        let count = style.count.min(budget);
+        budget -= count;
+        for i in 0..count {
+            let angle = match effect {
+                ParticleEffect::Landing => rand::random::<f32>() * PI,
+                _ => i as f32 / count as f32 * TAU,
+            };
+            spawn_particle(
+                &mut cmd,
+                style,
+                style.color(),
+                pos,
+                Vec2::from_angle(angle) * random_speed(style),
+            );
+        }
+    }
+}
+
+// 角色身后的拖尾, 颜色按玩家外观里的设置
+pub fn emit_trail(
+    mut cmd: Commands,
+    time: Res<Time>,
+    theme: Res<Theme>,
+    settings: Res<Settings>,
+    save: Res<SaveData>,
+    role: Single<&Transform, With<RoleSpeed>>,
+    particles: Query<(), With<Particle>>,
+    mut accum: Local<f32>,
+) {
+    let Some(style) = theme.manifest.particles.get(&ParticleEffect::Trail) else {
+        return;
+    };
+    let color = match save.profile.trail {
+        TrailKind::Theme => style.color(),
+        TrailKind::Primary => parse_color(Some(save.profile.primary.as_str())),
+        TrailKind::Secondary => parse_color(Some(save.profile.secondary.as_str())),
+        TrailKind::Off => return,
+    };
+    if settings.low_effects {
+        return;
+    }
+    *accum += style.rate * time.delta_secs();
+    let count = (*accum as usize).min(budget(&particles));
+    *accum = accum.fract();
+    let role_pos = role.translation.truncate();
+    for _ in 0..count {
+        let offset = Vec2::from_angle(rand::random::<f32>() * TAU)
+            * (rand::random::<f32>() * BALL_SIZE * 0.5);
+        let velocity = Vec2::from_angle(rand::random::<f32>() * TAU) * random_speed(style);
+        spawn_particle(&mut cmd, style, color, role_pos + offset, velocity);
+    }
+}
+
+// 给新生成的跳跃球和传送门挂上发射器
+pub fn attach_emitters(mut cmd: Commands, items: Query<(Entity, &ItemSkin), Added<ItemSkin>>) {
+    for (entity, skin) in items.iter() {
+        let effect = match skin {
+            ItemSkin::Orb => ParticleEffect::OrbGlow,
+            ItemSkin::PortalBegin | ItemSkin::PortalEnd => ParticleEffect::PortalShimmer,
+            _ => continue,
+        };
+        cmd.entity(entity)
+            .insert(ParticleEmitter { effect, accum: 0.0 });
+    }
+}
+
+// 跳跃球的光点从球面向外飘散, 传送门的微光在门内向上飘
+pub fn emit_item_particles(
+    mut cmd: Commands,
+    time: Res<Time>,
+    theme: Res<Theme>,
+    settings: Res<Settings>,
+    mut emitters: Query<(&Transform, &Collider, &mut ParticleEmitter)>,
+    particles: Query<(), With<Particle>>,
+) {
+    if settings.low_effects {
+        return;
+    }
+    let mut budget = budget(&particles);
+    for (transform, collider, mut emitter) in emitters.iter_mut() {
+        let Some(style) = theme.manifest.particles.get(&emitter.effect) else {
+            continue;
+        };
+        emitter.accum += style.rate * time.delta_secs();
+        let count = (emitter.accum as usize).min(budget);
+        emitter.accum = emitter.accum.fract();
+        budget -= count;
+        let center = transform.translation.truncate();
+        for _ in 0..count {
+            let (pos, velocity) = if let Some(ball) = collider.as_ball() {
+                let dir = Vec2::from_angle(rand::random::<f32>() * TAU);
+                (center + dir * ball.radius(), dir * random_speed(style))
+            } else if let Some(cuboid) = collider.as_cuboid() {
+                let half = cuboid.half_extents();
+                let offset =
+                    Vec2::new(rand::random::<f32>(), rand::random::<f32>()) * 2.0 - Vec2::ONE;
+                (center + offset * half, Vec2::Y * random_speed(style))
+            } else {
+                (center, Vec2::ZERO)
+            };
+            spawn_particle(&mut cmd, style, style.color(), pos, velocity);
+        }
+    }
+}
+
+pub fn particle_update(
+    mut cmd: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Transform, &mut Sprite, &mut Particle)>,
+) {
+    for (entity, mut transform, mut sprite, mut particle) in particles.iter_mut() {
+        particle.life.tick(time.delta());
+        if particle.life.is_finished() {
+            cmd.entity(entity).despawn();
+            continue;
+        }
+        particle.velocity.y -= particle.gravity * time.delta_secs();
+        transform.translation += (particle.velocity * time.delta_secs()).extend(0.0);
+        sprite.color.set_alpha(particle.life.fraction_remaining());
+    }
+}
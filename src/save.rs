@@ -0,0 +1,208 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fs::{create_dir_all, read_to_string, rename, write};
+use std::path::PathBuf;
+
+use crate::icon::DEFAULT_ICON;
+use crate::types::*;
+
+pub const SAVE_VERSION: u32 = 1;
+pub const SAVE_FILE: &str = "save.toml";
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct LevelRecord {
+    pub best_progress: f32,
+    pub completed: bool,
+    pub attempts: u32,
+    pub best_time: Option<f32>,
+    pub coins: u32,
+    pub practice_completed: bool,
+}
+
+// 拖尾颜色: 跟随主题、用玩家的主色或副色, 或者关掉
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TrailKind {
+    Theme,
+    Primary,
+    Secondary,
+    Off,
+}
+
+impl TrailKind {
+    pub const ALL: [TrailKind; 4] = [
+        TrailKind::Theme,
+        TrailKind::Primary,
+        TrailKind::Secondary,
+        TrailKind::Off,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TrailKind::Theme => "trail_theme",
+            TrailKind::Primary => "trail_primary",
+            TrailKind::Secondary => "trail_secondary",
+            TrailKind::Off => "trail_off",
+        }
+    }
+}
+
+// 玩家外观
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Profile {
+    // assets/icons 下的图标名
+    pub icon: String,
+    // 十六进制颜色
+    pub primary: String,
+    pub secondary: String,
+    pub trail: TrailKind,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            icon: DEFAULT_ICON.to_owned(),
+            primary: PLAYER_COLORS[0].to_owned(),
+            secondary: PLAYER_COLORS[1].to_owned(),
+            trail: TrailKind::Theme,
+        }
+    }
+}
+
+// 存档, 以关卡文件名(不含扩展名)为键
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    pub version: u32,
+    pub levels: HashMap<String, LevelRecord>,
+    pub profile: Profile,
+    // 存档版本比程序新时不写回, 避免丢掉新版本的数据
+    #[serde(skip)]
+    pub read_only: bool,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        Self {
+            version: SAVE_VERSION,
+            levels: HashMap::new(),
+            profile: Profile::default(),
+            read_only: false,
+        }
+    }
+}
+
+fn save_path() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("qpx"))
+        .unwrap_or_default()
+        .join(SAVE_FILE)
+}
+
+impl SaveData {
+    pub fn load() -> Self {
+        let path = save_path();
+        let Ok(file_data) = read_to_string(&path) else {
+            info!("no save file at {}", path.display());
+            return Self::default();
+        };
+        match toml::from_str::<SaveData>(&file_data) {
+            Ok(mut save) => {
+                if save.version > SAVE_VERSION {
+                    warn!(
+                        "save version {} is newer than {}, progress will not be written",
+                        save.version, SAVE_VERSION
+                    );
+                    save.read_only = true;
+                } else {
+                    // 旧版本缺少的字段由 serde(default) 补齐
+                    save.version = SAVE_VERSION;
+                }
+                save
+            }
+            Err(e) => {
+                warn!("corrupt save file {}: {}", path.display(), e);
+                let _ = rename(&path, path.with_extension("toml.bak"));
+                Self::default()
+            }
+        }
+    }
+
+    pub fn store(&self) {
+        if self.read_only {
+            return;
+        }
+        let path = save_path();
+        if let Some(dir) = path.parent() {
+            let _ = create_dir_all(dir);
+        }
+        match toml::to_string(self) {
+            Ok(s) => {
+                // 先写临时文件再改名, 写到一半退出时原存档不受影响
+                let tmp = path.with_extension("toml.tmp");
+                if let Err(e) = write(&tmp, s).and_then(|_| rename(&tmp, &path)) {
+                    warn!("write save file {} failed: {}", path.display(), e);
+                }
+            }
+            Err(e) => warn!("serialize save failed: {}", e),
+        }
+    }
+
+    pub fn record(&self, lv: &str) -> LevelRecord {
+        self.levels.get(lv).cloned().unwrap_or_default()
+    }
+}
+
+pub fn write_save(save: Res<SaveData>) {
+    save.store();
+}
+
+pub fn save_attempt(mut save: ResMut<SaveData>, lvs: Res<CurLevel>, practice: Res<Practice>) {
+    if practice.enabled {
+        return;
+    }
+    save.levels.entry(lvs.cur_name()).or_default().attempts += 1;
+}
+
+// 离开 Playing(暂停、死亡、通关)时记录本次运行中的最好进度
+pub fn save_progress(
+    mut save: ResMut<SaveData>,
+    lvs: Res<CurLevel>,
+    stats: Res<SessionStats>,
+    practice: Res<Practice>,
+) {
+    if practice.enabled {
+        return;
+    }
+    let lv = lvs.cur_name();
+    let best = stats.best_progress.get(&lv).copied().unwrap_or(0.0);
+    // 先只读比较, 没有提高时不标记存档改动, 避免每次暂停和死亡都重写存档
+    if best > save.record(&lv).best_progress {
+        save.levels.entry(lv).or_default().best_progress = best;
+    }
+}
+
+pub fn save_complete(
+    mut save: ResMut<SaveData>,
+    lvs: Res<CurLevel>,
+    run_stats: Res<RunStats>,
+    practice: Res<Practice>,
+) {
+    let record = save.levels.entry(lvs.cur_name()).or_default();
+    if practice.enabled {
+        record.practice_completed = true;
+        return;
+    }
+    record.completed = true;
+    record.best_progress = 100.0;
+    record.best_time = Some(
+        record
+            .best_time
+            .map_or(run_stats.time, |t| t.min(run_stats.time)),
+    );
+    record.coins = record.coins.max(run_stats.coins);
+}
@@ -0,0 +1,203 @@
+use bevy::prelude::*;
+use bevy::window::{MonitorSelection, PrimaryWindow, VideoModeSelection, WindowMode};
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::PathBuf;
+
+use crate::input::*;
+use crate::locale::*;
+use crate::types::{DEFAULT_TICK_HZ, MAX_TICK_HZ, MIN_TICK_HZ, SfxCategory, ShowHitboxes};
+
+pub const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WindowModeSetting {
+    #[default]
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl WindowModeSetting {
+    pub fn next(self) -> Self {
+        match self {
+            WindowModeSetting::Windowed => WindowModeSetting::Borderless,
+            WindowModeSetting::Borderless => WindowModeSetting::Fullscreen,
+            WindowModeSetting::Fullscreen => WindowModeSetting::Windowed,
+        }
+    }
+
+    // 字符串表里的 key
+    pub fn name(self) -> &'static str {
+        match self {
+            WindowModeSetting::Windowed => "window_windowed",
+            WindowModeSetting::Borderless => "window_borderless",
+            WindowModeSetting::Fullscreen => "window_fullscreen",
+        }
+    }
+
+    // 全屏使用的显示器: 创建窗口时窗口还不在任何显示器上, 用主显示器, 之后切换用当前所在的
+    pub fn to_window_mode(self, monitor: MonitorSelection) -> WindowMode {
+        match self {
+            WindowModeSetting::Windowed => WindowMode::Windowed,
+            WindowModeSetting::Borderless => WindowMode::BorderlessFullscreen(monitor),
+            WindowModeSetting::Fullscreen => {
+                WindowMode::Fullscreen(monitor, VideoModeSelection::Current)
+            }
+        }
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    // 各类音效在 sfx_volume 基础上的音量, 没有的按 1.0
+    pub sfx_categories: BTreeMap<SfxCategory, f32>,
+    pub window_mode: WindowModeSetting,
+    pub show_hitboxes: bool,
+    pub show_progress: bool,
+    // 进入关卡时是否倒计时
+    pub countdown: bool,
+    // 死亡到自动重开的间隔(秒)
+    pub respawn_delay: f32,
+    // 低特效: 不发射粒子
+    pub low_effects: bool,
+    // 物理和角色运动每秒的固定步长数
+    pub tick_rate: f64,
+    // assets/locales 下的语言文件名
+    pub language: String,
+    // 每个动作绑定的输入, 见 InputBinding::parse
+    pub bindings: BTreeMap<Action, Vec<String>>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            music_volume: 0.8,
+            sfx_volume: 0.8,
+            sfx_categories: BTreeMap::new(),
+            window_mode: WindowModeSetting::Windowed,
+            show_hitboxes: false,
+            show_progress: true,
+            countdown: true,
+            respawn_delay: 1.0,
+            low_effects: false,
+            tick_rate: DEFAULT_TICK_HZ,
+            language: DEFAULT_LANGUAGE.to_owned(),
+            bindings: default_bindings(),
+        }
+    }
+}
+
+fn config_path() -> PathBuf {
+    dirs::config_dir()
+        .map(|dir| dir.join("qpx"))
+        .unwrap_or_default()
+        .join(CONFIG_FILE)
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let path = config_path();
+        let Ok(file_data) = read_to_string(&path) else {
+            return Self::default();
+        };
+        toml::from_str(&file_data).unwrap_or_else(|e| {
+            warn!("invalid config {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn store(&self) {
+        let path = config_path();
+        if let Some(dir) = path.parent() {
+            let _ = create_dir_all(dir);
+        }
+        match toml::to_string(self) {
+            Ok(s) => {
+                if let Err(e) = write(&path, s) {
+                    warn!("write config {} failed: {}", path.display(), e);
+                }
+            }
+            Err(e) => warn!("serialize config failed: {}", e),
+        }
+    }
+
+    // 超出范围的配置按边界处理
+    pub fn tick_hz(&self) -> f64 {
+        self.tick_rate.clamp(MIN_TICK_HZ, MAX_TICK_HZ)
+    }
+
+    // rapier 每个固定步长走一步, 步长和 Time<Fixed> 保持一致
+    pub fn timestep_mode(&self) -> TimestepMode {
+        TimestepMode::Fixed {
+            dt: (1.0 / self.tick_hz()) as f32,
+            substeps: 1,
+        }
+    }
+
+    pub fn category_volume(&self, category: SfxCategory) -> f32 {
+        self.sfx_categories.get(&category).copied().unwrap_or(1.0)
+    }
+
+    // 旧配置里没有的动作使用默认绑定
+    pub fn bindings(&self, action: Action) -> Vec<InputBinding> {
+        let parse = |b: &Vec<String>| b.iter().filter_map(|s| InputBinding::parse(s)).collect();
+        match self.bindings.get(&action) {
+            Some(b) => parse(b),
+            None => default_bindings()
+                .get(&action)
+                .map(parse)
+                .unwrap_or_default(),
+        }
+    }
+
+    // 替换该动作上同类设备的绑定, 没有同类设备时追加
+    pub fn set_binding(&mut self, action: Action, binding: InputBinding) {
+        let Some(config) = binding.to_config() else {
+            return;
+        };
+        let bindings = self
+            .bindings
+            .entry(action)
+            .or_insert_with(|| default_bindings().remove(&action).unwrap_or_default());
+        bindings.retain(|s| InputBinding::parse(s).is_none_or(|b| !b.same_device(binding)));
+        bindings.push(config);
+    }
+}
+
+// 设置变化时应用到窗口、固定步长、碰撞框显示和界面语言, 并写回配置文件
+pub fn apply_settings(
+    settings: Res<Settings>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    mut applied_window_mode: Local<Option<WindowModeSetting>>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut timestep_mode: ResMut<TimestepMode>,
+    mut show_hitboxes: ResMut<ShowHitboxes>,
+    mut applied_hitboxes: Local<Option<bool>>,
+    mut locale: ResMut<Locale>,
+) {
+    // 启动时窗口已经按设置创建, 只在之后切换时修改
+    if applied_window_mode.is_some_and(|mode| mode != settings.window_mode) {
+        window.mode = settings
+            .window_mode
+            .to_window_mode(MonitorSelection::Current);
+    }
+    *applied_window_mode = Some(settings.window_mode);
+    fixed_time.set_timestep_hz(settings.tick_hz());
+    *timestep_mode = settings.timestep_mode();
+    // 只在设置里的开关变化时覆盖调试按键切换的状态
+    if *applied_hitboxes != Some(settings.show_hitboxes) {
+        *applied_hitboxes = Some(settings.show_hitboxes);
+        show_hitboxes.0 = settings.show_hitboxes;
+    }
+    if locale.lang != settings.language {
+        *locale = Locale::load(&settings.language);
+    }
+    settings.store();
+}
@@ -0,0 +1,4 @@
+// 游戏和关卡编辑器共用的常量, 不依赖 bevy, 编辑器用 #[path] 引入
+
+// 镜头固定显示的世界高度, 宽度随窗口比例变化; 界面按同样比例缩放
+pub const VIRTUAL_HEIGHT: f32 = 720.0;
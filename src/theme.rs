@@ -0,0 +1,264 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::mesh::{Indices, PrimitiveTopology};
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::fs::read_to_string;
+
+use crate::types::*;
+
+pub const THEME_PATH: &str = "assets/themes";
+pub const DEFAULT_THEME: &str = "default";
+
+// assets/themes/<主题名>/theme.toml, 里面的路径相对于主题目录
+#[derive(Deserialize, Default)]
+pub struct ThemeManifest {
+    #[serde(default)]
+    pub sfx: HashMap<GameEvent, String>,
+    #[serde(default)]
+    pub items: HashMap<ItemSkin, ItemStyle>,
+    // 没有配置的效果不发射粒子
+    #[serde(default)]
+    pub particles: HashMap<ParticleEffect, ParticleStyle>,
+}
+
+// 主题和关卡里的十六进制颜色, 不填或写错时为白色
+pub fn parse_color(color: Option<&str>) -> Color {
+    let Some(hex) = color else {
+        return Color::WHITE;
+    };
+    Srgba::hex(hex).map(Color::from).unwrap_or_else(|e| {
+        warn!("invalid color {}: {:?}", hex, e);
+        Color::WHITE
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ItemStyle {
+    #[serde(default)]
+    pub image: Option<String>,
+    // 十六进制颜色, 有贴图时作为染色
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub tile_x: bool,
+    #[serde(default)]
+    pub tile_y: bool,
+    // 平铺时每块贴图相对原尺寸的缩放
+    #[serde(default = "default_tile_scale")]
+    pub tile_scale: f32,
+    // 再按关卡里这个颜色通道的颜色染色
+    #[serde(default)]
+    pub channel: Option<ColorChannel>,
+}
+
+fn default_tile_scale() -> f32 {
+    1.0
+}
+
+impl ItemStyle {
+    pub fn color(&self) -> Color {
+        parse_color(self.color.as_deref())
+    }
+
+    fn image_mode(&self) -> SpriteImageMode {
+        if self.tile_x || self.tile_y {
+            SpriteImageMode::Tiled {
+                tile_x: self.tile_x,
+                tile_y: self.tile_y,
+                stretch_value: self.tile_scale,
+            }
+        } else {
+            SpriteImageMode::Auto
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Theme {
+    pub name: String,
+    pub manifest: ThemeManifest,
+}
+
+impl Theme {
+    pub fn load(name: &str) -> Self {
+        let path = format!("{}/{}/theme.toml", THEME_PATH, name);
+        let manifest = match read_to_string(&path) {
+            Ok(file_data) => toml::from_str(&file_data).unwrap_or_else(|e| {
+                warn!("invalid theme manifest {}: {}", path, e);
+                ThemeManifest::default()
+            }),
+            Err(e) => {
+                warn!("missing theme manifest {}: {}", path, e);
+                ThemeManifest::default()
+            }
+        };
+        Self {
+            name: name.to_owned(),
+            manifest,
+        }
+    }
+
+    // 主题内文件在 asset_server 下的路径
+    pub fn asset_path(&self, file: &str) -> String {
+        format!("themes/{}/{}", self.name, file)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ParticleStyle {
+    pub color: Option<String>,
+    pub size: f32,
+    // 存活时间(秒)
+    pub lifetime: f32,
+    // 初速度, 实际在 0.5~1 倍之间随机
+    pub speed: f32,
+    pub gravity: f32,
+    // 持续效果每秒发射的数量
+    pub rate: f32,
+    // 一次性效果发射的数量
+    pub count: usize,
+}
+
+impl Default for ParticleStyle {
+    fn default() -> Self {
+        Self {
+            color: None,
+            size: 6.0,
+            lifetime: 0.5,
+            speed: 0.0,
+            gravity: 0.0,
+            rate: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl ParticleStyle {
+    pub fn color(&self) -> Color {
+        parse_color(self.color.as_deref())
+    }
+}
+
+// 每种游戏事件对应的音效
+#[derive(Resource, Default)]
+pub struct SfxHandles(pub HashMap<GameEvent, Handle<AudioSource>>);
+
+pub fn load_theme_sfx(
+    theme: Res<Theme>,
+    asset_server: Res<AssetServer>,
+    mut sfx: ResMut<SfxHandles>,
+) {
+    sfx.0 = theme
+        .manifest
+        .sfx
+        .iter()
+        .map(|(event, file)| (*event, asset_server.load(theme.asset_path(file))))
+        .collect();
+}
+
+// 载入关卡时切换到关卡指定的主题
+pub fn apply_level_theme(level_data: Res<LevelData>, mut theme: ResMut<Theme>) {
+    let name = level_data.theme.as_deref().unwrap_or(DEFAULT_THEME);
+    if theme.name != name {
+        info!("switch theme to {}", name);
+        *theme = Theme::load(name);
+    }
+}
+
+// 凸多边形顶点按绕中心的角度逆时针排好后扇形三角化, 贴图拉伸到包围盒
+fn convex_mesh(points: &[Vec2]) -> Mesh {
+    let center = points.iter().sum::<Vec2>() / points.len() as f32;
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| {
+        (*a - center)
+            .to_angle()
+            .total_cmp(&(*b - center).to_angle())
+    });
+    let (min, max) = points.iter().fold((points[0], points[0]), |(min, max), p| {
+        (min.min(*p), max.max(*p))
+    });
+    let size = (max - min).max(Vec2::splat(f32::EPSILON));
+    let positions = points.iter().map(|p| [p.x, p.y, 0.0]).collect::<Vec<_>>();
+    // 图片 y 轴向下
+    let uvs = points
+        .iter()
+        .map(|p| [(p.x - min.x) / size.x, (max.y - p.y) / size.y])
+        .collect::<Vec<_>>();
+    let normals = vec![[0.0, 0.0, 1.0]; points.len()];
+    let indices = (1..points.len() as u32 - 1)
+        .flat_map(|i| [0, i, i + 1])
+        .collect::<Vec<u32>>();
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+// 新生成的道具按碰撞体形状和主题加上外观: 矩形和圆用精灵, 三角形和多边形用网格
+pub fn skin_map_items(
+    mut cmd: Commands,
+    theme: Res<Theme>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    items: Query<(Entity, &ItemSkin, &Collider, Has<ItemChannel>), Added<ItemSkin>>,
+) {
+    for (entity, skin, collider, has_channel) in items.iter() {
+        let Some(style) = theme.manifest.items.get(skin) else {
+            continue;
+        };
+        // 关卡里单独指定的通道优先
+        if let Some(channel) = style.channel
+            && !has_channel
+        {
+            cmd.entity(entity).insert(ItemChannel(channel));
+        }
+        let tint = style.color();
+        let image = style
+            .image
+            .as_ref()
+            .map(|file| asset_server.load(theme.asset_path(file)));
+        let size = if let Some(cuboid) = collider.as_cuboid() {
+            Some(cuboid.half_extents() * 2.0)
+        } else {
+            collider
+                .as_ball()
+                .map(|ball| Vec2::splat(ball.radius() * 2.0))
+        };
+        if let Some(size) = size {
+            cmd.entity(entity).insert(Sprite {
+                image: image.unwrap_or_default(),
+                color: tint,
+                custom_size: Some(size),
+                image_mode: style.image_mode(),
+                ..default()
+            });
+        } else {
+            let points = if let Some(tri) = collider.as_triangle() {
+                vec![tri.a(), tri.b(), tri.c()]
+            } else if let Some(polygon) = collider.as_convex_polygon() {
+                polygon.points().collect()
+            } else {
+                warn!("no skin for collider shape of {:?}", skin);
+                continue;
+            };
+            cmd.entity(entity).insert((
+                Mesh2d(meshes.add(convex_mesh(&points))),
+                MeshMaterial2d(materials.add(ColorMaterial {
+                    color: tint,
+                    texture: image,
+                    ..default()
+                })),
+            ));
+        }
+        cmd.entity(entity).insert(ItemTint(tint));
+    }
+}
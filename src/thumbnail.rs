@@ -0,0 +1,126 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::types::*;
+
+pub const THUMBNAIL_W: u32 = 200;
+pub const THUMBNAIL_H: u32 = 100;
+const THUMBNAIL_BG: Color = Color::srgb(0.08, 0.08, 0.12);
+
+fn item_color(item: &MapItemData) -> Color {
+    match item {
+        MapItemData::Floor(_) | MapItemData::Polygon(_, false) | MapItemData::Slope(_) => {
+            Color::srgb(0.75, 0.75, 0.75)
+        }
+        MapItemData::TriObstacle(_)
+        | MapItemData::RectObstacle(_)
+        | MapItemData::Polygon(_, true) => Color::srgb(0.9, 0.25, 0.25),
+        MapItemData::DoubleJumpCircle(..) => Color::srgb(0.95, 0.85, 0.2),
+        MapItemData::RectFlyBegin(_) | MapItemData::RectFlyEnd(_) => Color::srgb(0.3, 0.5, 0.95),
+        MapItemData::RectPass(_) => Color::srgb(0.3, 0.85, 0.3),
+        MapItemData::Coin(..) => Color::srgb(1.0, 0.75, 0.1),
+    }
+}
+
+// 多边形顶点按绕中心的角度排序后做射线法判断, 与生成碰撞体时取凸包一致
+fn polygon_contains(points: &[Vec2], p: Vec2) -> bool {
+    let center = points.iter().sum::<Vec2>() / points.len() as f32;
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| {
+        (*a - center)
+            .to_angle()
+            .total_cmp(&(*b - center).to_angle())
+    });
+    let mut inside = false;
+    for i in 0..sorted.len() {
+        let a = sorted[i];
+        let b = sorted[(i + 1) % sorted.len()];
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn item_contains(item: &MapItemData, p: Vec2) -> bool {
+    match item {
+        MapItemData::Floor(rect)
+        | MapItemData::RectObstacle(rect)
+        | MapItemData::RectFlyBegin(rect)
+        | MapItemData::RectFlyEnd(rect)
+        | MapItemData::RectPass(rect) => {
+            (p.x - rect.x).abs() <= rect.z && (p.y - rect.y).abs() <= rect.w
+        }
+        MapItemData::TriObstacle(tri) => polygon_contains(&tri.vertices, p),
+        MapItemData::Polygon(points, _) | MapItemData::Slope(points) => polygon_contains(points, p),
+        MapItemData::DoubleJumpCircle(pos, radius) | MapItemData::Coin(pos, radius) => {
+            pos.distance(p) <= *radius
+        }
+    }
+}
+
+/// 把关卡几何画成缩略图, 横竖分别缩放到整张图, 关卡越长横向压得越扁
+pub fn render_thumbnail(level: &LevelData) -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: THUMBNAIL_W,
+            height: THUMBNAIL_H,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &THUMBNAIL_BG.to_srgba().to_u8_array(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    let (min, max) = level.data.iter().map(|item| item.aabb()).fold(
+        (ROLE_START, ROLE_START),
+        |(min, max), (item_min, item_max)| (min.min(item_min), max.max(item_max)),
+    );
+    let size = (max - min).max(Vec2::ONE);
+    let scale = Vec2::new(THUMBNAIL_W as f32, THUMBNAIL_H as f32) / size;
+    // 图片 y 轴向下
+    let to_pixel = |p: Vec2| Vec2::new((p.x - min.x) * scale.x, (max.y - p.y) * scale.y);
+    let to_world = |px: Vec2| Vec2::new(px.x / scale.x + min.x, max.y - px.y / scale.y);
+
+    for item in level.data.iter() {
+        let color = item_color(item);
+        let (item_min, item_max) = item.aabb();
+        let top_left = to_pixel(Vec2::new(item_min.x, item_max.y)).floor();
+        let bottom_right = to_pixel(Vec2::new(item_max.x, item_min.y)).ceil();
+        let x0 = (top_left.x as u32).min(THUMBNAIL_W - 1);
+        let y0 = (top_left.y as u32).min(THUMBNAIL_H - 1);
+        let x1 = (bottom_right.x as u32).clamp(x0 + 1, THUMBNAIL_W);
+        let y1 = (bottom_right.y as u32).clamp(y0 + 1, THUMBNAIL_H);
+        // 不到两个像素的道具直接填满包围盒, 否则采样时可能整个消失
+        let tiny = x1 - x0 < 2 || y1 - y0 < 2;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let center = to_world(Vec2::new(x as f32 + 0.5, y as f32 + 0.5));
+                if tiny || item_contains(item, center) {
+                    let _ = image.set_color_at(x, y, color);
+                }
+            }
+        }
+    }
+    image
+}
+
+// 启动时为每个关卡生成缩略图, 初始状态的 OnEnter 早于 Startup, 所以在插入资源时就生成
+impl FromWorld for LevelThumbnails {
+    fn from_world(world: &mut World) -> Self {
+        let lvs = world.resource::<CurLevel>().lvs.clone();
+        let mut images = world.resource_mut::<Assets<Image>>();
+        Self(
+            lvs.iter()
+                .map(|lv| match LevelData::from_file(lv) {
+                    Ok(level) => Some(images.add(render_thumbnail(&level))),
+                    Err(e) => {
+                        warn!("invalid level {}: {}", lv, e);
+                        None
+                    }
+                })
+                .collect(),
+        )
+    }
+}
@@ -2,20 +2,83 @@ use bevy::math::prelude::*;
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs::{read_dir, read_to_string};
 use std::path::Path;
 
+use crate::input::Action;
+pub use crate::shared::VIRTUAL_HEIGHT;
+use crate::theme::parse_color;
+
 pub const FLOOR_H: f32 = 20.0;
 pub const JUMP_SPEED: f32 = 600.0;
 pub const ROLE_SPEED: f32 = 300.0;
 pub const GRAVITY: f32 = 1300.0;
 pub const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
+pub const SELECTED_BUTTON: Color = Color::srgb(0.3, 0.35, 0.5);
 pub const BALL_SIZE: f32 = 30.0;
 pub const LV_DATA_PATH: &str = "level_data";
+// 窗口初始大小, 窗口可以自由缩放
 pub const WINDOW_RESOLUTION_X: u32 = 1280;
 pub const WINDOW_RESOLUTION_Y: u32 = 720;
+// 法线 y 分量大于该值的接触面视为可站立的地面(约 60 度以内的坡)
+pub const GROUND_NORMAL_MIN_Y: f32 = 0.5;
+// 角色控制器贴地吸附距离
+pub const GROUND_SNAP: f32 = 8.0;
+// 物理和角色运动的固定步长频率, 默认值和配置允许的范围
+pub const DEFAULT_TICK_HZ: f64 = 120.0;
+pub const MIN_TICK_HZ: f64 = 30.0;
+pub const MAX_TICK_HZ: f64 = 480.0;
+// 镜头相对角色的水平偏移
+pub const CAMERA_OFFSET_X: f32 = 100.0;
+pub const ROLE_START: Vec2 = Vec2::new(-100.0, 200.0);
+// 角色偏离镜头中心超过该距离时镜头才开始竖直跟随
+pub const CAMERA_DEAD_ZONE_Y: f32 = 120.0;
+// 镜头竖直跟随的平滑系数, 越大跟得越紧
+pub const CAMERA_SMOOTHING: f32 = 5.0;
+// 竖直方向超出屏幕该距离的道具才销毁, 避免在边界反复创建销毁
+pub const CULL_MARGIN_Y: f32 = 200.0;
+// 道具创建和销毁的水平半宽, 按 32:9 的超宽窗口取, 和实际窗口大小无关
+pub const CULL_HALF_X: f32 = VIRTUAL_HEIGHT * 32.0 / 9.0 / 2.0;
+// 玩家图标的大小, 空中旋转的角速度(一次跳跃约半圈)和落地后对齐到 90 度的速度
+pub const ICON_SIZE: f32 = BALL_SIZE * 2.0;
+pub const ICON_SPIN_SPEED: f32 = std::f32::consts::PI * GRAVITY / (2.0 * JUMP_SPEED);
+pub const ICON_SNAP_SPEED: f32 = 20.0;
+// 玩家主色和副色可选的颜色
+pub const PLAYER_COLORS: [&str; 10] = [
+    "#ffd23f", "#3fa7ff", "#ff5a5f", "#5fd068", "#b57bff", "#ff9f1c", "#2ec4b6", "#ffffff",
+    "#ff70a6", "#1b1b1e",
+];
+// 背景层的 z, 按层的顺序依次往前
+pub const BACKGROUND_Z: f32 = -100.0;
+// 同时存在的粒子上限, 超出后不再发射
+pub const MAX_PARTICLES: usize = 600;
+// 进入关卡后的倒计时(秒)
+pub const COUNTDOWN_SECS: f32 = 3.0;
+// 选关网格每行的卡片数、卡片尺寸和可见行数
+pub const LEVEL_GRID_COLUMNS: usize = 4;
+pub const LEVEL_CARD_W: f32 = 216.0;
+pub const LEVEL_CARD_H: f32 = 190.0;
+pub const LEVEL_GRID_GAP: f32 = 12.0;
+pub const LEVEL_GRID_ROWS: usize = 2;
+// 鼠标滚轮按行滚动时每行的像素数
+pub const SCROLL_LINE_H: f32 = 40.0;
+// 键盘/手柄聚焦的菜单项的描边
+pub const FOCUS_OUTLINE: Color = Color::srgb(0.9, 0.8, 0.3);
+// 设置界面每行的高度和行距, 焦点移动时据此滚动
+pub const SETTINGS_ROW_H: f32 = 32.0;
+pub const SETTINGS_ROW_GAP: f32 = 4.0;
+pub const BACKGROUND_COLOR: Color = Color::srgb(0.1, 0.1, 0.16);
+// 背景随音乐振幅变亮时趋向的颜色
+pub const BACKGROUND_PULSE_COLOR: Color = Color::srgb(0.22, 0.16, 0.34);
+// 节拍脉冲的衰减时间(秒)
+pub const BEAT_DECAY: f32 = 0.2;
+// 跟随节拍的道具在两拍之间的亮度
+pub const PULSE_DIM: f32 = 0.7;
+// 角色光晕半径, 以及节拍上放大的比例
+pub const GLOW_RADIUS: f32 = BALL_SIZE * 1.6;
+pub const GLOW_PULSE_SCALE: f32 = 0.35;
 
 #[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum GameState {
@@ -24,15 +87,38 @@ pub enum GameState {
     InitLevel,
     Playing,
     Paused,
+    Dead,
+    LevelComplete,
+    Settings,
+    Customize,
 }
 
 #[derive(Component)]
 pub struct RoleSpeed(pub f32, pub f32);
 
+// 角色在前后两个固定步长结束时的位置, 渲染时按 overstep 插值
 #[derive(Component)]
+pub struct SimPosition {
+    pub prev: Vec2,
+    pub cur: Vec2,
+}
+
+impl SimPosition {
+    // overstep 为 Time<Fixed> 中未满一个步长的比例
+    pub fn interpolated(&self, overstep: f32) -> Vec2 {
+        self.prev.lerp(self.cur, overstep)
+    }
+}
+
+// 角色的显示部分(图标、光晕), 作为角色的子实体按插值位置偏移, 碰撞体仍在模拟位置
+#[derive(Component)]
+pub struct RoleVisual;
+
+#[derive(Component, Clone, Copy, Debug)]
 pub enum RoleState {
     Air(u32),
-    Normal,
+    // 站在地面或坡面上, 参数为接触面法线, 平地为 Vec2::Y
+    Normal(Vec2),
 }
 
 #[derive(Bundle)]
@@ -51,32 +137,397 @@ pub enum MapItem {
     FlyBegin,
     FlyEnd,
     Pass,
+    Slope,
+    Coin,
 }
 
+// 道具在主题里对应的外观, 主题清单 [items.<名字>] 的键
+#[derive(Component, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemSkin {
+    FloorTop,
+    Floor,
+    Block,
+    Spike,
+    Ground,
+    Obstacle,
+    Slope,
+    Orb,
+    Coin,
+    PortalBegin,
+    PortalEnd,
+    Pass,
+}
+
+// 道具贴图或网格的基础颜色, 节拍闪动等效果在此基础上调整
 #[derive(Component)]
-pub struct StartGameButton;
+pub struct ItemTint(pub Color);
 
+// 关卡里除地图道具和角色外的实体(尝试次数文字、粒子等), 离开关卡时一起销毁
 #[derive(Component)]
-pub struct LeftSelectButton;
+pub struct LevelDecor;
 
 #[derive(Component)]
-pub struct RightSelectButton;
+pub struct AttemptLabel;
+
+#[derive(Component)]
+pub struct Particle {
+    pub velocity: Vec2,
+    pub gravity: f32,
+    pub life: Timer,
+}
+
+// 粒子效果, 主题清单 [particles.<名字>] 的键
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticleEffect {
+    Trail,
+    Landing,
+    OrbGlow,
+    PortalShimmer,
+    Death,
+}
+
+// 持续发射粒子的道具, accum 为还没发出的粒子数
+#[derive(Component)]
+pub struct ParticleEmitter {
+    pub effect: ParticleEffect,
+    pub accum: f32,
+}
+
+#[derive(Component)]
+pub struct StartGameButton;
 
 #[derive(Component)]
 pub struct ReturnMainMenuButton;
 
+#[derive(Component)]
+pub struct ReplayButton;
+
+#[derive(Component)]
+pub struct NextLevelButton;
+
+#[derive(Component)]
+pub struct SettingsButton;
+
+#[derive(Component)]
+pub struct ResumeButton;
+
+#[derive(Component)]
+pub struct RestartButton;
+
+#[derive(Component)]
+pub struct PracticeToggleButton;
+
 #[derive(Component)]
 pub struct MainUIEntity;
 
+#[derive(Component)]
+pub struct SettingsUIEntity;
+
+#[derive(Component)]
+pub struct CustomizeButton;
+
+#[derive(Component)]
+pub struct CustomizeUIEntity;
+
+// 外观界面里可以切换的项
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CustomizeField {
+    Icon,
+    Primary,
+    Secondary,
+    Trail,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CustomizeAction {
+    Prev(CustomizeField),
+    Next(CustomizeField),
+    Back,
+}
+
+#[derive(Component)]
+pub struct CustomizeItem(pub CustomizeAction);
+
+#[derive(Component)]
+pub struct CustomizeValue(pub CustomizeField);
+
+// 外观界面的图标预览, 参数为是否副色层
+#[derive(Component)]
+pub struct IconPreview(pub bool);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SettingField {
+    MusicVolume,
+    SfxVolume,
+    WindowMode,
+    Hitboxes,
+    ProgressBar,
+    Countdown,
+    RespawnDelay,
+    LowEffects,
+    Language,
+    SfxCategoryVolume(SfxCategory),
+    Binding(Action),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SettingAction {
+    Decrease(SettingField),
+    Increase(SettingField),
+    Toggle(SettingField),
+    ResetBindings,
+    Back,
+}
+
+#[derive(Component)]
+pub struct SettingsItem(pub SettingAction);
+
+// 显示某个设置当前值的文字
+#[derive(Component)]
+pub struct SettingsValue(pub SettingField);
+
+#[derive(Component)]
+pub struct LevelCompleteUIEntity;
+
 #[derive(Component)]
 pub struct PauseUIEntity;
 
+// 主菜单里的关卡卡片, 参数为 CurLevel.lvs 的下标
+#[derive(Component)]
+pub struct LevelCard(pub usize);
+
+// 关卡卡片所在的可滚动网格
 #[derive(Component)]
-pub struct CurLvLabel;
+pub struct LevelGrid;
+
+// 可以用鼠标滚轮滚动的界面区域
+#[derive(Component)]
+pub struct ScrollArea;
+
+// 菜单里可以用方向动作聚焦的按钮或设置行, 参数为上下移动的顺序
+#[derive(Component)]
+pub struct MenuFocusItem(pub usize);
+
+// 菜单里按暂停键等同按下这个按钮
+#[derive(Component)]
+pub struct MenuBackButton;
+
+// 键盘/手柄在当前菜单里的焦点, 主菜单里为 None 时焦点在关卡网格上
+#[derive(Resource, Default)]
+pub struct MenuFocus {
+    pub idx: Option<usize>,
+    // 本帧由动作按下的按钮, 帧末松开
+    pub pressed: Option<Entity>,
+}
+
+#[derive(Component)]
+pub struct HudUIEntity;
+
+#[derive(Component)]
+pub struct CountdownUIEntity;
+
+#[derive(Component)]
+pub struct CountdownLabel;
+
+#[derive(Component)]
+pub struct ProgressFill;
+
+#[derive(Component)]
+pub struct ProgressLabel;
+
+#[derive(Component)]
+pub struct HudAttemptLabel;
+
+#[derive(Component)]
+pub struct BestProgressLabel;
 
 #[derive(Deserialize)]
 pub struct LevelDataOrigin {
-    data: Vec<(u32, Vec<f32>)>,
+    data: Vec<ItemOrigin>,
+    // 镜头竖直方向的范围, 不填时下限为 0, 上限不限
+    #[serde(default)]
+    camera_min_y: Option<f32>,
+    #[serde(default)]
+    camera_max_y: Option<f32>,
+    // 关卡音乐(assets 下的路径), 以及关卡开始时对应的音乐时间(秒)
+    #[serde(default)]
+    music: Option<String>,
+    #[serde(default)]
+    music_offset: f32,
+    // 离线分析音乐得到的节拍文件(assets 下的路径)
+    #[serde(default)]
+    beat_map: Option<String>,
+    // 使用的主题, 不填时为默认主题
+    #[serde(default)]
+    theme: Option<String>,
+    #[serde(default)]
+    background: Vec<BackgroundLayerData>,
+    #[serde(default)]
+    color_triggers: Vec<ColorTriggerData>,
+    // 颜色通道的初始颜色, 如 [colors] ground = "#6b4a2f"
+    #[serde(default)]
+    colors: HashMap<ColorChannel, String>,
+}
+
+// 颜色通道, 主题贴图按所属通道染色, 通道颜色由关卡指定并可被触发器改变
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub enum ColorChannel {
+    Background,
+    Ground,
+    Line,
+    Obstacle,
+    // custom1, custom2, ...
+    Custom(u32),
+}
+
+impl TryFrom<String> for ColorChannel {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        match name.as_str() {
+            "background" => Ok(ColorChannel::Background),
+            "ground" => Ok(ColorChannel::Ground),
+            "line" => Ok(ColorChannel::Line),
+            "obstacle" => Ok(ColorChannel::Obstacle),
+            _ => name
+                .strip_prefix("custom")
+                .and_then(|n| n.parse().ok())
+                .filter(|n| *n >= 1)
+                .map(ColorChannel::Custom)
+                .ok_or_else(|| format!("unknown color channel {}", name)),
+        }
+    }
+}
+
+// 视差背景层, 关卡文件里的 [[background]], 按顺序从后往前画
+#[derive(Deserialize, Clone)]
+pub struct BackgroundLayerData {
+    // assets 下的图片路径
+    pub image: String,
+    // 跟随世界移动的比例, 0 为固定在屏幕上, 1 为和道具一起移动
+    #[serde(default)]
+    pub scroll: f32,
+    // 十六进制染色
+    #[serde(default)]
+    pub tint: Option<String>,
+    #[serde(default)]
+    pub tile_x: bool,
+    #[serde(default)]
+    pub tile_y: bool,
+    #[serde(default = "default_layer_scale")]
+    pub scale: f32,
+    // 图片中心在 scroll 为 1 时对应的世界坐标
+    #[serde(default)]
+    pub x: f32,
+    #[serde(default)]
+    pub y: f32,
+}
+
+fn default_layer_scale() -> f32 {
+    1.0
+}
+
+// 颜色触发器, 关卡文件里的 [[color_triggers]], 角色经过 x 时把背景层或颜色通道渐变到新颜色
+#[derive(Deserialize, Clone)]
+pub struct ColorTriggerData {
+    pub x: f32,
+    // 背景层下标
+    #[serde(default)]
+    pub layer: Option<usize>,
+    #[serde(default)]
+    pub channel: Option<ColorChannel>,
+    pub color: String,
+    // 渐变时间(秒), 0 为立即变色
+    #[serde(default)]
+    pub duration: f32,
+}
+
+// 道具可以在类型和参数后面带一个标记表, 如 [2, [0, 0, 50, 50], { pulse = true }]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ItemOrigin {
+    Plain(u32, Vec<f32>),
+    Flagged(u32, Vec<f32>, ItemFlags),
+}
+
+#[derive(Deserialize, Default)]
+struct ItemFlags {
+    // 跟随音乐节拍闪动
+    #[serde(default)]
+    pulse: bool,
+    // 使用的颜色通道, 不填时按主题
+    #[serde(default)]
+    channel: Option<ColorChannel>,
+}
+
+// 由频谱/振幅分析离线生成的节拍文件
+#[derive(Deserialize, Default)]
+pub struct BeatMap {
+    // 每个节拍的时间(秒)和强度(0~1), 按时间排序
+    #[serde(default)]
+    pub beats: Vec<(f32, f32)>,
+    // 按固定间隔采样的音量包络(0~1)
+    #[serde(default)]
+    pub envelope_interval: f32,
+    #[serde(default)]
+    pub envelope: Vec<f32>,
+}
+
+impl BeatMap {
+    pub fn load(path: &str) -> Self {
+        let path = Path::new("assets").join(path);
+        let Ok(file_data) = read_to_string(&path) else {
+            warn!("missing beat map {}", path.display());
+            return Self::default();
+        };
+        match toml::from_str::<BeatMap>(&file_data) {
+            Ok(mut beat_map) => {
+                beat_map.beats.sort_by(|a, b| a.0.total_cmp(&b.0));
+                beat_map
+            }
+            Err(e) => {
+                warn!("invalid beat map {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    // 最近一拍的强度按时间指数衰减
+    pub fn beat(&self, t: f32) -> f32 {
+        let idx = self.beats.partition_point(|(beat_t, _)| *beat_t <= t);
+        if idx == 0 {
+            return 0.0;
+        }
+        let (beat_t, strength) = self.beats[idx - 1];
+        strength * (-(t - beat_t) / BEAT_DECAY).exp()
+    }
+
+    // 包络相邻采样线性插值
+    pub fn amplitude(&self, t: f32) -> f32 {
+        if self.envelope.is_empty() || self.envelope_interval <= 0.0 || t < 0.0 {
+            return 0.0;
+        }
+        let pos = t / self.envelope_interval;
+        let i = pos as usize;
+        let Some(a) = self.envelope.get(i) else {
+            return 0.0;
+        };
+        let b = self.envelope.get(i + 1).unwrap_or(a);
+        a + (b - a) * pos.fract()
+    }
+}
+
+// 关卡文件里的展示信息, 选关界面使用
+#[derive(Deserialize, Default)]
+pub struct LevelInfo {
+    // 显示名, 不填时用文件名
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub difficulty: Option<u32>,
 }
 
 pub enum MapItemData {
@@ -87,13 +538,240 @@ pub enum MapItemData {
     RectFlyBegin(Vec4),
     RectFlyEnd(Vec4),
     RectPass(Vec4),
+    Polygon(Vec<Vec2>, bool),
+    Slope(Vec<Vec2>),
+    Coin(Vec2, f32),
 }
 
 #[derive(Resource, Default)]
 pub struct LevelData {
     pub data: Vec<MapItemData>,
+    pub camera_min_y: f32,
+    pub camera_max_y: f32,
+    pub music: Option<String>,
+    pub music_offset: f32,
+    // 带 pulse 标记的道具下标
+    pub pulse: HashSet<u32>,
+    pub beat_map: BeatMap,
+    pub theme: Option<String>,
+    pub background: Vec<BackgroundLayerData>,
+    // 按 x 排序
+    pub color_triggers: Vec<ColorTriggerData>,
+    pub colors: HashMap<ColorChannel, Color>,
+    // 单独指定了颜色通道的道具下标
+    pub channels: HashMap<u32, ColorChannel>,
+}
+
+// 死亡时的镜头抖动, 重开前的等待时间在设置里调整
+#[derive(Resource)]
+pub struct DeathConfig {
+    pub shake_strength: f32,
+}
+
+impl Default for DeathConfig {
+    fn default() -> Self {
+        Self {
+            shake_strength: 12.0,
+        }
+    }
+}
+
+// 死亡后到重开前的计时, 以及镜头抖动的中心位置
+#[derive(Resource)]
+pub struct DeathSequence {
+    pub timer: Timer,
+    pub shake_origin: Vec3,
+}
+
+// 本次运行中每个关卡的尝试次数和最好进度(百分比), 和存档一样以关卡文件名(不含扩展名)为键
+#[derive(Resource, Default)]
+pub struct SessionStats {
+    pub attempts: HashMap<String, u32>,
+    pub best_progress: HashMap<String, f32>,
+    // 死亡重开时跳过等待直接开始
+    pub respawn: bool,
+}
+
+// 本次尝试的统计, 每次进入关卡时重置
+#[derive(Resource, Default)]
+pub struct RunStats {
+    pub time: f32,
+    pub jumps: u32,
+    pub coins: u32,
+    // 已经吃掉的金币在关卡数据中的下标, 不再重新创建
+    pub collected: HashSet<u32>,
+}
+
+// 练习模式下放置的检查点, 死亡后从最后一个检查点继续
+#[derive(Clone, Copy)]
+pub struct Checkpoint {
+    pub pos: Vec2,
+    pub speed: Vec2,
+    pub state: RoleState,
+    pub camera_y: f32,
+    // 放置时的关卡时间, 从检查点继续时音乐从这里接着播
+    pub time: f32,
+}
+
+// 练习模式不计入存档的尝试次数和最好进度, 通关只记为练习通关
+#[derive(Resource, Default)]
+pub struct Practice {
+    pub enabled: bool,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+// 当前是否显示碰撞框, 启动时取设置里的值, 调试按键只改这里不写回配置
+#[derive(Resource)]
+pub struct ShowHitboxes(pub bool);
+
+// 设置界面返回时回到的状态(主菜单或暂停)
+#[derive(Resource)]
+pub struct SettingsOrigin(pub GameState);
+
+impl Default for SettingsOrigin {
+    fn default() -> Self {
+        Self(GameState::Main)
+    }
+}
+
+// 玩法中发生的事件, 音效等表现由各自的系统读取后处理
+#[derive(Message, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GameEvent {
+    Jump,
+    Land,
+    OrbUsed,
+    Death,
+    PortalEntered,
+    CoinCollected,
+    LevelComplete,
+}
+
+// 重开本关的请求, 快捷键和暂停、通关界面的按钮共用同一套清理流程
+#[derive(Message)]
+pub struct RestartLevel;
+
+// 音效音量分类, 每类在设置里单独调节
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum SfxCategory {
+    Player,
+    Pickup,
+    Level,
+}
+
+impl SfxCategory {
+    pub const ALL: [SfxCategory; 3] =
+        [SfxCategory::Player, SfxCategory::Pickup, SfxCategory::Level];
+
+    // 设置界面上的名字在字符串表里的 key
+    pub fn name(self) -> &'static str {
+        match self {
+            SfxCategory::Player => "setting_sfx_player",
+            SfxCategory::Pickup => "setting_sfx_pickup",
+            SfxCategory::Level => "setting_sfx_level",
+        }
+    }
 }
 
+impl GameEvent {
+    pub fn category(self) -> SfxCategory {
+        match self {
+            GameEvent::Jump | GameEvent::Land | GameEvent::OrbUsed | GameEvent::PortalEntered => {
+                SfxCategory::Player
+            }
+            GameEvent::CoinCollected => SfxCategory::Pickup,
+            GameEvent::Death | GameEvent::LevelComplete => SfxCategory::Level,
+        }
+    }
+}
+
+// 当前关卡音乐应播放到的位置(秒), 按模拟时间推进, 画面和玩法据此对拍
+#[derive(Resource, Default)]
+pub struct MusicTime(pub f32);
+
+#[derive(Component)]
+pub struct LevelMusic;
+
+#[derive(Component)]
+pub struct BackgroundLayer {
+    pub index: usize,
+    pub fade: Option<ColorFade>,
+}
+
+// 颜色触发器引起的渐变
+pub struct ColorFade {
+    pub from: Color,
+    pub to: Color,
+    pub timer: Timer,
+}
+
+// 下一个未触发的颜色触发器; instant 时直接变色, 用于从检查点开始时补上已经经过的触发器
+#[derive(Resource, Default)]
+pub struct ColorTriggerState {
+    pub next: usize,
+    pub instant: bool,
+}
+
+// 道具所属的颜色通道
+#[derive(Component)]
+pub struct ItemChannel(pub ColorChannel);
+
+// 颜色通道当前的颜色和正在进行的渐变
+#[derive(Resource, Default)]
+pub struct ColorChannels {
+    pub colors: HashMap<ColorChannel, Color>,
+    pub fades: HashMap<ColorChannel, ColorFade>,
+}
+
+impl ColorChannels {
+    // 没有指定的通道不染色, 背景用默认背景色
+    pub fn get(&self, channel: ColorChannel) -> Color {
+        self.colors.get(&channel).copied().unwrap_or(match channel {
+            ColorChannel::Background => BACKGROUND_COLOR,
+            _ => Color::WHITE,
+        })
+    }
+}
+
+// 跟随节拍闪动的道具
+#[derive(Component)]
+pub struct Pulse;
+
+#[derive(Component)]
+pub struct DebugOverlayUIEntity;
+
+#[derive(Component)]
+pub struct DebugOverlayLabel;
+
+// 角色的图标, 主色层, 副色层是它的子实体
+#[derive(Component)]
+pub struct PlayerIcon;
+
+// 角色身后的光晕
+#[derive(Component)]
+pub struct PlayerGlow;
+
+// 当前音乐时间对应的节拍强度和音量, 每帧由 MusicTime 算出
+#[derive(Resource, Default)]
+pub struct BeatPulse {
+    pub beat: f32,
+    pub amplitude: f32,
+}
+
+// 开始前的倒计时, 结束后进入 Playing
+#[derive(Resource)]
+pub struct Countdown {
+    pub timer: Timer,
+}
+
+// 设置界面中正在等待按键的绑定项
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<SettingField>);
+
+// Update 中记录的跳跃输入, 由下一个固定步长消费
+#[derive(Resource, Default)]
+pub struct JumpInput(pub bool);
+
 #[derive(Resource, Default)]
 pub struct IdxEntityPair {
     pub pairs: HashMap<u32, (Entity, Option<Entity>)>,
@@ -102,26 +780,117 @@ pub struct IdxEntityPair {
 #[derive(Resource)]
 pub struct CurLevel {
     pub lvs: Vec<String>,
+    pub infos: Vec<LevelInfo>,
     pub cur_idx: usize,
 }
 
+// 每个关卡的缩略图, 下标与 CurLevel.lvs 一致, 关卡文件无效时为 None
+#[derive(Resource)]
+pub struct LevelThumbnails(pub Vec<Option<Handle<Image>>>);
+
 impl Default for CurLevel {
     fn default() -> Self {
         let dirs = read_dir(LV_DATA_PATH).unwrap();
         let lvs = dirs
             .map(|e| e.unwrap().path().to_str().to_owned().unwrap().to_string())
             .collect::<Vec<String>>();
-        Self { lvs, cur_idx: 0 }
+        let infos = lvs
+            .iter()
+            .map(|lv| {
+                read_to_string(lv)
+                    .ok()
+                    .and_then(|s| toml::from_str(&s).ok())
+                    .unwrap_or_default()
+            })
+            .collect();
+        Self {
+            lvs,
+            infos,
+            cur_idx: 0,
+        }
+    }
+}
+
+impl CurLevel {
+    // 当前关卡文件名(不含扩展名)
+    pub fn cur_name(&self) -> String {
+        self.name(self.cur_idx)
+    }
+
+    // 关卡文件名(不含扩展名), 也是存档的键
+    pub fn name(&self, idx: usize) -> String {
+        Path::new(self.lvs[idx].as_str())
+            .file_stem()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    // 界面上显示的关卡名
+    pub fn display_name(&self, idx: usize) -> String {
+        self.infos[idx]
+            .name
+            .clone()
+            .unwrap_or_else(|| self.name(idx))
     }
 }
 
 impl LevelData {
-    pub fn from_file(path: impl AsRef<Path>) -> Self {
-        let file_data = read_to_string(path).unwrap();
-        let level_data_origin: LevelDataOrigin = toml::from_str(&file_data).unwrap();
+    // 关卡文件读取或解析失败时返回错误信息, 由调用方跳过该关卡
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let file_data = read_to_string(path).map_err(|e| e.to_string())?;
+        let level_data_origin: LevelDataOrigin =
+            toml::from_str(&file_data).map_err(|e| e.to_string())?;
         let mut data = Vec::new();
-        for (typ, v) in level_data_origin.data {
-            if v.len() == 4 {
+        let mut pulse = HashSet::new();
+        let mut channels = HashMap::new();
+        let mut color_triggers = level_data_origin.color_triggers;
+        color_triggers.sort_by(|a, b| a.x.total_cmp(&b.x));
+        for (i, item) in level_data_origin.data.into_iter().enumerate() {
+            let (typ, v, flags) = match item {
+                ItemOrigin::Plain(typ, v) => (typ, v, ItemFlags::default()),
+                ItemOrigin::Flagged(typ, v, flags) => (typ, v, flags),
+            };
+            // 7: 凸多边形障碍, 8: 凸多边形地面, 9: 坡, 顶点按 x0 y0 x1 y1 ... 排列
+            let is_polygon = typ == 7 || typ == 8 || typ == 9;
+            if is_polygon && (v.len() % 2 != 0 || v.len() < 6) {
+                warn!("skip polygon item #{}: needs at least 3 x y pairs", i);
+                continue;
+            }
+            // 只有地面(0)和障碍(2)可以旋转
+            if !is_polygon && v.len() == 5 && typ != 0 && typ != 2 {
+                warn!("skip rotated item #{}: type {} cannot be rotated", i, typ);
+                continue;
+            }
+            // 跳过的道具不占下标
+            let idx = data.len() as u32;
+            if flags.pulse {
+                pulse.insert(idx);
+            }
+            if let Some(channel) = flags.channel {
+                channels.insert(idx, channel);
+            }
+            if is_polygon {
+                let points = v
+                    .chunks_exact(2)
+                    .map(|p| Vec2::new(p[0], p[1]))
+                    .collect::<Vec<Vec2>>();
+                if typ == 9 {
+                    data.push(MapItemData::Slope(points));
+                } else {
+                    data.push(MapItemData::Polygon(points, typ == 7));
+                }
+            } else if v.len() == 5 {
+                // 旋转矩形: x y 半宽 半高 弧度, 转成四边形处理
+                let rot = Rot2::radians(v[4]);
+                let center = Vec2::new(v[0], v[1]);
+                let points = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                    .iter()
+                    .map(|(sx, sy)| center + rot * Vec2::new(sx * v[2], sy * v[3]))
+                    .collect::<Vec<Vec2>>();
+                data.push(MapItemData::Polygon(points, typ == 2));
+            } else if v.len() == 4 {
                 let rect = Vec4::new(v[0], v[1], v[2], v[3]);
                 if typ == 0 {
                     data.push(MapItemData::Floor(rect));
@@ -141,15 +910,104 @@ impl LevelData {
                     Vec2::new(v[4], v[5]),
                 )));
             } else if v.len() == 3 {
-                data.push(MapItemData::DoubleJumpCircle(Vec2::new(v[0], v[1]), v[2]));
+                if typ == 10 {
+                    data.push(MapItemData::Coin(Vec2::new(v[0], v[1]), v[2]));
+                } else {
+                    data.push(MapItemData::DoubleJumpCircle(Vec2::new(v[0], v[1]), v[2]));
+                }
             } else {
-                panic!();
+                return Err(format!("item #{} has {} values", i, v.len()));
+            }
+        }
+        Ok(Self {
+            data,
+            camera_min_y: level_data_origin.camera_min_y.unwrap_or(0.0),
+            camera_max_y: level_data_origin.camera_max_y.unwrap_or(f32::MAX),
+            music: level_data_origin.music,
+            music_offset: level_data_origin.music_offset,
+            pulse,
+            beat_map: level_data_origin
+                .beat_map
+                .as_deref()
+                .map(BeatMap::load)
+                .unwrap_or_default(),
+            theme: level_data_origin.theme,
+            background: level_data_origin.background,
+            color_triggers,
+            colors: level_data_origin
+                .colors
+                .iter()
+                .map(|(channel, color)| (*channel, parse_color(Some(color.as_str()))))
+                .collect(),
+            channels,
+        })
+    }
+
+    // 以终点道具左边界为 100%, 没有终点时返回 0
+    pub fn progress(&self, x: f32) -> f32 {
+        let pass_x = self.data.iter().find_map(|item| match item {
+            MapItemData::RectPass(rect) => Some(rect.x - rect.z),
+            _ => None,
+        });
+        let Some(pass_x) = pass_x else {
+            return 0.0;
+        };
+        if pass_x <= ROLE_START.x {
+            return 100.0;
+        }
+        ((x - ROLE_START.x) / (pass_x - ROLE_START.x)).clamp(0.0, 1.0) * 100.0
+    }
+
+    pub fn clamp_camera_y(&self, y: f32) -> f32 {
+        y.clamp(self.camera_min_y, self.camera_max_y.max(self.camera_min_y))
+    }
+}
+
+impl MapItemData {
+    // 调试信息里显示的类型名
+    pub fn name(&self) -> &'static str {
+        match self {
+            MapItemData::Floor(_) => "floor",
+            MapItemData::TriObstacle(_) => "spike",
+            MapItemData::RectObstacle(_) => "block",
+            MapItemData::DoubleJumpCircle(..) => "orb",
+            MapItemData::RectFlyBegin(_) => "fly begin",
+            MapItemData::RectFlyEnd(_) => "fly end",
+            MapItemData::RectPass(_) => "pass",
+            MapItemData::Polygon(_, true) => "polygon obstacle",
+            MapItemData::Polygon(_, false) => "polygon",
+            MapItemData::Slope(_) => "slope",
+            MapItemData::Coin(..) => "coin",
+        }
+    }
+
+    /// 包围盒 (min, max), 用于随镜头创建和销毁
+    pub fn aabb(&self) -> (Vec2, Vec2) {
+        match self {
+            MapItemData::Floor(rect)
+            | MapItemData::RectObstacle(rect)
+            | MapItemData::RectFlyBegin(rect)
+            | MapItemData::RectFlyEnd(rect)
+            | MapItemData::RectPass(rect) => (
+                Vec2::new(rect.x - rect.z, rect.y - rect.w),
+                Vec2::new(rect.x + rect.z, rect.y + rect.w),
+            ),
+            MapItemData::TriObstacle(tri) => points_aabb(&tri.vertices),
+            MapItemData::Polygon(points, _) | MapItemData::Slope(points) => points_aabb(points),
+            MapItemData::DoubleJumpCircle(pos, radius) | MapItemData::Coin(pos, radius) => {
+                (*pos - Vec2::splat(*radius), *pos + Vec2::splat(*radius))
             }
         }
-        Self { data }
     }
 }
 
+fn points_aabb(points: &[Vec2]) -> (Vec2, Vec2) {
+    points.iter().fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), p| (min.min(*p), max.max(*p)),
+    )
+}
+
 impl MapItemBundle {
     pub fn rect_item(rect: &Vec4, obstacle: bool) -> Self {
         Self {
@@ -201,6 +1059,41 @@ impl MapItemBundle {
         }
     }
 
+    pub fn polygon(points: &[Vec2], obstacle: bool) -> Option<Self> {
+        Self::convex(
+            points,
+            if obstacle {
+                MapItem::Obstacle
+            } else {
+                MapItem::Normal
+            },
+        )
+    }
+
+    pub fn slope(points: &[Vec2]) -> Option<Self> {
+        Self::convex(points, MapItem::Slope)
+    }
+
+    fn convex(points: &[Vec2], map_item: MapItem) -> Option<Self> {
+        let origin = *points.first()?;
+        let local = points.iter().map(|p| *p - origin).collect::<Vec<Vec2>>();
+        Some(Self {
+            rigid: RigidBody::Fixed,
+            collider: Collider::convex_hull(&local)?,
+            position: Transform::from_xyz(origin.x, origin.y, 0.0),
+            map_item,
+        })
+    }
+
+    pub fn circle_coin(pos: &Vec2, radius: f32) -> Self {
+        Self {
+            rigid: RigidBody::Fixed,
+            collider: Collider::ball(radius),
+            position: Transform::from_xyz(pos.x, pos.y, 0.0),
+            map_item: MapItem::Coin,
+        }
+    }
+
     pub fn circle_double_jump(pos: &Vec2, radius: f32) -> Self {
         info!("spawn circle");
         Self {
@@ -1,9 +1,50 @@
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
 use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
 
+use crate::game::despawn_level;
+use crate::icon::*;
+use crate::input::*;
+use crate::locale::*;
+use crate::save::*;
+use crate::settings::*;
+use crate::theme::parse_color;
+use crate::thumbnail::*;
 use crate::types::*;
-use std::path::Path;
 
-pub fn main_ui(mut cmd: Commands, lvs: Res<CurLevel>) {
+fn record_text(locale: &Locale, record: &LevelRecord) -> String {
+    format!(
+        "{}{}",
+        if record.completed { "* " } else { "" },
+        locale.format(
+            "record",
+            &[
+                format!("{:.0}", record.best_progress),
+                record.attempts.to_string(),
+                record.coins.to_string(),
+            ]
+        )
+    )
+}
+
+fn difficulty_text(locale: &Locale, info: &LevelInfo) -> String {
+    match info.difficulty {
+        Some(d) => locale.format("difficulty", &[d.to_string()]),
+        None => locale.get("unrated"),
+    }
+}
+
+fn grid_view_h() -> f32 {
+    LEVEL_GRID_ROWS as f32 * (LEVEL_CARD_H + LEVEL_GRID_GAP) - LEVEL_GRID_GAP
+}
+
+pub fn main_ui(
+    mut cmd: Commands,
+    lvs: Res<CurLevel>,
+    save: Res<SaveData>,
+    thumbnails: Res<LevelThumbnails>,
+    locale: Res<Locale>,
+) {
     let btn_bundle = (
         Button,
         Node {
@@ -24,63 +65,174 @@ pub fn main_ui(mut cmd: Commands, lvs: Res<CurLevel>) {
         },
         TextColor(Color::srgb(0.9, 0.9, 0.9)),
     );
+    let small_text = |size: f32| {
+        (
+            TextFont {
+                font_size: size,
+                ..default()
+            },
+            TextColor(Color::srgb(0.7, 0.7, 0.7)),
+        )
+    };
 
     cmd.spawn(Node {
         // center button
+        flex_direction: FlexDirection::Column,
         width: Val::Percent(100.),
         height: Val::Percent(100.),
         justify_content: JustifyContent::Center,
         align_items: AlignItems::Center,
+        row_gap: Val::Px(20.),
         ..default()
     })
     .with_children(|parent| {
-        for i in [
-            "<",
-            Path::new(lvs.lvs[lvs.cur_idx].as_str())
-                .file_stem()
-                .unwrap()
-                .to_str()
-                .unwrap(),
-            ">",
-            "start",
-        ] {
-            match i {
-                "<" => {
-                    parent
-                        .spawn((btn_bundle.clone(), LeftSelectButton))
-                        .with_children(|parent| {
-                            parent.spawn((text_bundle.clone(), Text::new(i)));
-                        });
-                }
-                ">" => {
-                    parent
-                        .spawn((btn_bundle.clone(), RightSelectButton))
-                        .with_children(|parent| {
-                            parent.spawn((text_bundle.clone(), Text::new(i)));
-                        });
-                }
-                "start" => {
+        parent
+            .spawn((
+                Node {
+                    display: Display::Grid,
+                    grid_template_columns: RepeatedGridTrack::px(
+                        LEVEL_GRID_COLUMNS as u16,
+                        LEVEL_CARD_W,
+                    ),
+                    grid_auto_rows: vec![GridTrack::px(LEVEL_CARD_H)],
+                    row_gap: Val::Px(LEVEL_GRID_GAP),
+                    column_gap: Val::Px(LEVEL_GRID_GAP),
+                    max_height: Val::Px(grid_view_h()),
+                    overflow: Overflow::scroll_y(),
+                    ..default()
+                },
+                ScrollPosition::default(),
+                ScrollArea,
+                LevelGrid,
+            ))
+            .with_children(|parent| {
+                for idx in 0..lvs.lvs.len() {
+                    let bg = if idx == lvs.cur_idx {
+                        SELECTED_BUTTON
+                    } else {
+                        NORMAL_BUTTON
+                    };
                     parent
-                        .spawn((btn_bundle.clone(), StartGameButton))
+                        .spawn((
+                            Button,
+                            Node {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                padding: UiRect::all(Val::Px(8.)),
+                                row_gap: Val::Px(4.),
+                                ..default()
+                            },
+                            BackgroundColor(bg),
+                            LevelCard(idx),
+                        ))
                         .with_children(|parent| {
-                            parent.spawn((text_bundle.clone(), Text::new(i)));
+                            let thumbnail_node = Node {
+                                width: Val::Px(THUMBNAIL_W as f32),
+                                height: Val::Px(THUMBNAIL_H as f32),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            };
+                            match &thumbnails.0[idx] {
+                                Some(thumbnail) => {
+                                    parent
+                                        .spawn((ImageNode::new(thumbnail.clone()), thumbnail_node));
+                                }
+                                // 关卡文件无效, 用占位图代替缩略图
+                                None => {
+                                    parent
+                                        .spawn((
+                                            thumbnail_node,
+                                            BackgroundColor(Color::srgb(0.3, 0.1, 0.1)),
+                                        ))
+                                        .with_children(|parent| {
+                                            parent.spawn((
+                                                Text::new(locale.get("level_invalid")),
+                                                Localized("level_invalid"),
+                                                small_text(16.0),
+                                            ));
+                                        });
+                                }
+                            }
+                            parent.spawn((
+                                Text::new(lvs.display_name(idx)),
+                                TextFont {
+                                    font_size: 22.0,
+                                    ..default()
+                                },
+                                TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                            ));
+                            parent.spawn((
+                                Text::new(difficulty_text(&locale, &lvs.infos[idx])),
+                                small_text(16.0),
+                            ));
+                            parent.spawn((
+                                Text::new(record_text(&locale, &save.record(&lvs.name(idx)))),
+                                small_text(14.0),
+                            ));
                         });
                 }
-                s => {
-                    parent.spawn((Text::new(s), CurLvLabel, text_bundle.clone()));
-                }
-            }
-        }
+            });
+        parent
+            .spawn(Node {
+                column_gap: Val::Px(10.),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn((btn_bundle.clone(), StartGameButton, MenuFocusItem(0)))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            text_bundle.clone(),
+                            Text::new(locale.get("start")),
+                            Localized("start"),
+                        ));
+                    });
+                parent
+                    .spawn((btn_bundle.clone(), CustomizeButton, MenuFocusItem(1)))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            text_bundle.clone(),
+                            Text::new(locale.get("customize")),
+                            Localized("customize"),
+                        ));
+                    });
+                parent
+                    .spawn((btn_bundle.clone(), SettingsButton, MenuFocusItem(2)))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            text_bundle.clone(),
+                            Text::new(locale.get("settings")),
+                            Localized("settings"),
+                        ));
+                    });
+            });
     })
     .insert(MainUIEntity);
 }
 
-pub fn pause_ui(mut cmd: Commands) {
+fn practice_text(locale: &Locale, practice: &Practice) -> String {
+    locale.get(if practice.enabled {
+        "practice_on"
+    } else {
+        "practice_off"
+    })
+}
+
+pub fn pause_ui(
+    mut cmd: Commands,
+    role: Single<&SimPosition>,
+    level_data: Res<LevelData>,
+    lvs: Res<CurLevel>,
+    stats: Res<SessionStats>,
+    practice: Res<Practice>,
+    locale: Res<Locale>,
+) {
     let btn_bundle = (
         Button,
         Node {
-            width: Val::Px(150.),
-            height: Val::Px(65.),
+            width: Val::Px(240.),
+            height: Val::Px(55.),
             // horizontally center child text
             justify_content: JustifyContent::Center,
             // vertically center child text
@@ -97,6 +249,13 @@ pub fn pause_ui(mut cmd: Commands) {
         TextColor(Color::srgb(0.9, 0.9, 0.9)),
     );
 
+    let progress = level_data.progress(role.cur.x);
+    let best = stats
+        .best_progress
+        .get(&lvs.cur_name())
+        .copied()
+        .unwrap_or(0.0);
+
     cmd.spawn(Node {
         // center button
         flex_direction: FlexDirection::Column,
@@ -104,19 +263,287 @@ pub fn pause_ui(mut cmd: Commands) {
         height: Val::Percent(100.),
         justify_content: JustifyContent::Center,
         align_items: AlignItems::Center,
+        row_gap: Val::Px(10.),
         ..default()
     })
     .with_children(|parent| {
+        parent.spawn((
+            text_bundle.clone(),
+            Text::new(locale.format(
+                "pause_progress",
+                &[format!("{:.0}", progress), format!("{:.0}", best)],
+            )),
+        ));
         parent
-            .spawn((btn_bundle.clone(), ReturnMainMenuButton))
+            .spawn((btn_bundle.clone(), ResumeButton, MenuFocusItem(0)))
             .with_children(|parent| {
-                parent.spawn((text_bundle.clone(), Text::new("return")));
+                parent.spawn((
+                    text_bundle.clone(),
+                    Text::new(locale.get("resume")),
+                    Localized("resume"),
+                ));
+            });
+        parent
+            .spawn((btn_bundle.clone(), RestartButton, MenuFocusItem(1)))
+            .with_children(|parent| {
+                parent.spawn((
+                    text_bundle.clone(),
+                    Text::new(locale.get("restart")),
+                    Localized("restart"),
+                ));
+            });
+        parent
+            .spawn((btn_bundle.clone(), PracticeToggleButton, MenuFocusItem(2)))
+            .with_children(|parent| {
+                parent.spawn((
+                    text_bundle.clone(),
+                    Text::new(practice_text(&locale, &practice)),
+                ));
+            });
+        parent
+            .spawn((btn_bundle.clone(), SettingsButton, MenuFocusItem(3)))
+            .with_children(|parent| {
+                parent.spawn((
+                    text_bundle.clone(),
+                    Text::new(locale.get("settings")),
+                    Localized("settings"),
+                ));
+            });
+        parent
+            .spawn((btn_bundle.clone(), ReturnMainMenuButton, MenuFocusItem(4)))
+            .with_children(|parent| {
+                parent.spawn((
+                    text_bundle.clone(),
+                    Text::new(locale.get("return")),
+                    Localized("return"),
+                ));
             });
-        parent.spawn((text_bundle.clone(), Text::new("press ECS to continue")));
     })
     .insert(PauseUIEntity);
 }
 
+pub fn hud_ui(mut cmd: Commands, settings: Res<Settings>) {
+    let text_bundle = (
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.9, 0.9, 0.9)),
+    );
+
+    cmd.spawn(Node {
+        flex_direction: FlexDirection::Column,
+        width: Val::Percent(100.),
+        padding: UiRect::all(Val::Px(10.)),
+        align_items: AlignItems::Center,
+        ..default()
+    })
+    .with_children(|parent| {
+        let progress_display = if settings.show_progress {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        parent
+            .spawn(Node {
+                width: Val::Percent(50.),
+                justify_content: JustifyContent::SpaceBetween,
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn((text_bundle.clone(), Text::new(""), HudAttemptLabel));
+                parent.spawn((
+                    text_bundle.clone(),
+                    Text::new(""),
+                    Node {
+                        display: progress_display,
+                        ..default()
+                    },
+                    ProgressLabel,
+                ));
+                parent.spawn((text_bundle.clone(), Text::new(""), BestProgressLabel));
+            });
+        parent
+            .spawn((
+                Node {
+                    display: progress_display,
+                    width: Val::Percent(50.),
+                    height: Val::Px(12.),
+                    ..default()
+                },
+                BackgroundColor(NORMAL_BUTTON),
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    Node {
+                        width: Val::Percent(0.),
+                        height: Val::Percent(100.),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.3, 0.8, 0.3)),
+                    ProgressFill,
+                ));
+            });
+    })
+    .insert(HudUIEntity);
+}
+
+pub fn hud_update(
+    role: Single<&SimPosition>,
+    level_data: Res<LevelData>,
+    lvs: Res<CurLevel>,
+    mut stats: ResMut<SessionStats>,
+    mut progress_fill: Single<&mut Node, With<ProgressFill>>,
+    mut progress_text: Single<
+        &mut Text,
+        (
+            With<ProgressLabel>,
+            Without<HudAttemptLabel>,
+            Without<BestProgressLabel>,
+        ),
+    >,
+    mut attempt_text: Single<&mut Text, (With<HudAttemptLabel>, Without<BestProgressLabel>)>,
+    mut best_text: Single<&mut Text, With<BestProgressLabel>>,
+    practice: Res<Practice>,
+    locale: Res<Locale>,
+) {
+    let lv = lvs.cur_name();
+    let progress = level_data.progress(role.cur.x);
+    let best = stats.best_progress.entry(lv.clone()).or_insert(0.0);
+    // 练习模式的进度不算最好成绩
+    if !practice.enabled {
+        *best = best.max(progress);
+    }
+
+    progress_fill.width = Val::Percent(progress);
+    progress_text.0 = format!("{:.0}%", progress);
+    best_text.0 = locale.format("hud_best", &[format!("{:.0}", best)]);
+    attempt_text.0 = if practice.enabled {
+        locale.format(
+            "practice_checkpoints",
+            &[practice.checkpoints.len().to_string()],
+        )
+    } else {
+        locale.format(
+            "attempt",
+            &[stats.attempts.get(&lv).copied().unwrap_or(0).to_string()],
+        )
+    };
+}
+
+pub fn leave_hud(mut cmd: Commands, hud_ui: Single<Entity, With<HudUIEntity>>) {
+    cmd.entity(*hud_ui).despawn();
+}
+
+pub fn level_complete_ui(
+    mut cmd: Commands,
+    run_stats: Res<RunStats>,
+    lvs: Res<CurLevel>,
+    mut stats: ResMut<SessionStats>,
+    practice: Res<Practice>,
+    locale: Res<Locale>,
+) {
+    let btn_bundle = (
+        Button,
+        Node {
+            width: Val::Px(150.),
+            height: Val::Px(65.),
+            // horizontally center child text
+            justify_content: JustifyContent::Center,
+            // vertically center child text
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(NORMAL_BUTTON),
+    );
+    let text_bundle = (
+        TextFont {
+            font_size: 33.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.9, 0.9, 0.9)),
+    );
+
+    let lv = lvs.cur_name();
+    if !practice.enabled {
+        stats.best_progress.insert(lv.clone(), 100.0);
+    }
+    let attempts = stats.attempts.get(&lv).copied().unwrap_or(0);
+
+    cmd.spawn(Node {
+        flex_direction: FlexDirection::Column,
+        width: Val::Percent(100.),
+        height: Val::Percent(100.),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        row_gap: Val::Px(10.),
+        ..default()
+    })
+    .with_children(|parent| {
+        let title = if practice.enabled {
+            "practice_complete"
+        } else {
+            "level_complete"
+        };
+        parent.spawn((text_bundle.clone(), Text::new(locale.get(title))));
+        for line in [
+            locale.format("stat_time", &[format!("{:.2}", run_stats.time)]),
+            locale.format("stat_attempts", &[attempts.to_string()]),
+            locale.format("stat_jumps", &[run_stats.jumps.to_string()]),
+            locale.format("stat_coins", &[run_stats.coins.to_string()]),
+        ] {
+            parent.spawn((text_bundle.clone(), Text::new(line)));
+        }
+        parent
+            .spawn(Node {
+                column_gap: Val::Px(10.),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn((btn_bundle.clone(), ReplayButton, MenuFocusItem(0)))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            text_bundle.clone(),
+                            Text::new(locale.get("replay")),
+                            Localized("replay"),
+                        ));
+                    });
+                parent
+                    .spawn((btn_bundle.clone(), NextLevelButton, MenuFocusItem(1)))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            text_bundle.clone(),
+                            Text::new(locale.get("next")),
+                            Localized("next"),
+                        ));
+                    });
+                parent
+                    .spawn((
+                        btn_bundle.clone(),
+                        ReturnMainMenuButton,
+                        MenuFocusItem(2),
+                        MenuBackButton,
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            text_bundle.clone(),
+                            Text::new(locale.get("return")),
+                            Localized("return"),
+                        ));
+                    });
+            });
+    })
+    .insert(LevelCompleteUIEntity);
+}
+
+pub fn leave_level_complete(
+    mut cmd: Commands,
+    level_complete_ui: Single<Entity, With<LevelCompleteUIEntity>>,
+) {
+    cmd.entity(*level_complete_ui).despawn();
+}
+
 pub fn leave_pause(mut cmd: Commands, pause_ui: Single<Entity, With<PauseUIEntity>>) {
     cmd.entity(*pause_ui).despawn();
 }
@@ -127,91 +554,1008 @@ pub fn leave_main(mut cmd: Commands, main_ui: Single<Entity, With<MainUIEntity>>
 
 pub fn start_button_action(
     start_button: Query<&Interaction, (Changed<Interaction>, With<StartGameButton>)>,
+    action_state: Res<ActionState>,
+    focus: Res<MenuFocus>,
     mut next_state: ResMut<NextState<GameState>>,
     lvs: Res<CurLevel>,
     mut lvd: ResMut<LevelData>,
 ) {
-    let Ok(interaction) = start_button.single() else {
+    let pressed = matches!(start_button.single(), Ok(Interaction::Pressed));
+    // 焦点在底部按钮上时确认由 menu_focus_navigate 按下对应按钮
+    let confirmed = focus.idx.is_none() && action_state.just_pressed(Action::Confirm);
+    if pressed || confirmed {
+        let lv = &lvs.lvs[lvs.cur_idx];
+        match LevelData::from_file(lv) {
+            Ok(level) => {
+                info!("start game");
+                *lvd = level;
+                next_state.set(GameState::InitLevel);
+            }
+            Err(e) => warn!("invalid level {}: {}", lv, e),
+        }
+    }
+}
+
+pub fn level_card_action(
+    cards: Query<(&Interaction, &LevelCard), Changed<Interaction>>,
+    mut lvs: ResMut<CurLevel>,
+) {
+    for (interaction, card) in cards.iter() {
+        if *interaction == Interaction::Pressed && lvs.cur_idx != card.0 {
+            lvs.cur_idx = card.0;
+        }
+    }
+}
+
+// 用方向动作在网格里移动选中的关卡, 左右在首尾之间循环
+pub fn level_grid_navigate(
+    action_state: Res<ActionState>,
+    mut lvs: ResMut<CurLevel>,
+    mut focus: ResMut<MenuFocus>,
+) {
+    // 焦点在底部按钮上时由 menu_focus_navigate 处理, 向上回到关卡网格
+    if focus.idx.is_some() {
+        if action_state.just_pressed(Action::MenuUp) {
+            focus.idx = None;
+        }
         return;
+    }
+    let len = lvs.lvs.len();
+    let cur = lvs.cur_idx;
+    // 最后一行再向下移到底部按钮
+    if action_state.just_pressed(Action::MenuDown)
+        && cur / LEVEL_GRID_COLUMNS == (len - 1) / LEVEL_GRID_COLUMNS
+    {
+        focus.idx = Some(0);
+        return;
+    }
+    let nxt = if action_state.just_pressed(Action::MenuLeft) {
+        (cur + len - 1) % len
+    } else if action_state.just_pressed(Action::MenuRight) {
+        (cur + 1) % len
+    } else if action_state.just_pressed(Action::MenuUp) {
+        cur.checked_sub(LEVEL_GRID_COLUMNS).unwrap_or(cur)
+    } else if action_state.just_pressed(Action::MenuDown) {
+        if cur + LEVEL_GRID_COLUMNS < len {
+            cur + LEVEL_GRID_COLUMNS
+        } else {
+            cur
+        }
+    } else {
+        cur
     };
-    if let Interaction::Pressed = interaction {
-        info!("start game");
-        *lvd = LevelData::from_file(&lvs.lvs[lvs.cur_idx]);
-        next_state.set(GameState::InitLevel);
+    if nxt != cur {
+        lvs.cur_idx = nxt;
     }
 }
 
-pub fn select_lv_left_button_action(
-    left_select_btn: Query<&Interaction, (Changed<Interaction>, With<LeftSelectButton>)>,
-    mut lvs: ResMut<CurLevel>,
-    cur_lv_text: Single<&mut Text, With<CurLvLabel>>,
+// 选中关卡变化时更新卡片高亮, 并把选中的卡片滚动到可见范围
+pub fn level_grid_select(
+    lvs: Res<CurLevel>,
+    mut cards: Query<(&LevelCard, &mut BackgroundColor)>,
+    mut grid: Single<&mut ScrollPosition, With<LevelGrid>>,
 ) {
-    let Ok(interaction) = left_select_btn.single() else {
+    for (card, mut bg) in cards.iter_mut() {
+        bg.0 = if card.0 == lvs.cur_idx {
+            SELECTED_BUTTON
+        } else {
+            NORMAL_BUTTON
+        };
+    }
+    let row_top = (lvs.cur_idx / LEVEL_GRID_COLUMNS) as f32 * (LEVEL_CARD_H + LEVEL_GRID_GAP);
+    if row_top < grid.y {
+        grid.y = row_top;
+    } else if row_top + LEVEL_CARD_H > grid.y + grid_view_h() {
+        grid.y = row_top + LEVEL_CARD_H - grid_view_h();
+    }
+}
+
+// 进入菜单时焦点在第一项上, 主菜单的焦点在关卡网格上
+pub fn reset_menu_focus(state: Res<State<GameState>>, mut focus: ResMut<MenuFocus>) {
+    *focus = MenuFocus {
+        idx: (*state.get() != GameState::Main).then_some(0),
+        pressed: None,
+    };
+}
+
+// 模拟一次点击, 按钮自己的 action 系统照常处理
+fn press_button(entity: Entity, buttons: &mut Query<&mut Interaction>, focus: &mut MenuFocus) {
+    if let Ok(mut interaction) = buttons.get_mut(entity) {
+        *interaction = Interaction::Pressed;
+        focus.pressed = Some(entity);
+    }
+}
+
+// 菜单项本身是按钮时就是它, 设置行则是行里的按钮
+fn focus_buttons(
+    entity: Entity,
+    children: &Query<&Children>,
+    buttons: &Query<&mut Interaction>,
+) -> Vec<Entity> {
+    if buttons.contains(entity) {
+        return vec![entity];
+    }
+    children
+        .iter_descendants(entity)
+        .filter(|e| buttons.contains(*e))
+        .collect()
+}
+
+// 上下移动焦点, 左右调整设置行的值, 确认按下焦点按钮, 暂停键等同返回
+pub fn menu_focus_navigate(
+    action_state: Res<ActionState>,
+    state: Res<State<GameState>>,
+    rebinding: Res<Rebinding>,
+    mut focus: ResMut<MenuFocus>,
+    items: Query<(Entity, &MenuFocusItem)>,
+    back: Query<Entity, With<MenuBackButton>>,
+    children: Query<&Children>,
+    mut buttons: Query<&mut Interaction>,
+) {
+    // 改绑时按键交给 capture_rebind, 刚改绑完的这一帧也不处理
+    if rebinding.0.is_some() || rebinding.is_changed() {
+        return;
+    }
+    if action_state.just_pressed(Action::Pause) {
+        if let Ok(entity) = back.single() {
+            press_button(entity, &mut buttons, &mut focus);
+        }
+        return;
+    }
+    let Some(cur) = focus.idx else {
         return;
     };
-    if let Interaction::Pressed = interaction {
-        if lvs.cur_idx == 0 {
-            lvs.cur_idx = lvs.lvs.len() - 1;
+    let len = items.iter().count();
+    let Some((entity, _)) = items.iter().find(|(_, item)| item.0 == cur) else {
+        return;
+    };
+    let targets = focus_buttons(entity, &children, &buttons);
+    let is_row = targets.first() != Some(&entity);
+    let left = action_state.just_pressed(Action::MenuLeft);
+    let right = action_state.just_pressed(Action::MenuRight);
+    // 主菜单底部按钮横排, 上下由 level_grid_navigate 在网格和按钮间切换
+    let vertical = *state.get() != GameState::Main;
+    let up = vertical && action_state.just_pressed(Action::MenuUp);
+    let down = vertical && action_state.just_pressed(Action::MenuDown);
+    if up || (left && !is_row) {
+        focus.idx = Some((cur + len - 1) % len);
+    } else if down || (right && !is_row) {
+        focus.idx = Some((cur + 1) % len);
+    } else if left || right {
+        let target = if left {
+            targets.first()
         } else {
-            lvs.cur_idx -= 1;
+            targets.last()
+        };
+        if let Some(&target) = target {
+            press_button(target, &mut buttons, &mut focus);
         }
-        let mut text = cur_lv_text.into_inner();
-        **text = Path::new(lvs.lvs[lvs.cur_idx].as_str())
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_owned();
+    } else if action_state.just_pressed(Action::Confirm)
+        && let [target] = targets[..]
+    {
+        press_button(target, &mut buttons, &mut focus);
     }
 }
 
-pub fn select_lv_right_button_action(
-    right_select_btn: Query<&Interaction, (Changed<Interaction>, With<RightSelectButton>)>,
-    mut lvs: ResMut<CurLevel>,
-    cur_lv_text: Single<&mut Text, With<CurLvLabel>>,
+// 给焦点项加描边, 设置界面里把焦点行滚动到可见范围
+pub fn menu_focus_select(
+    mut cmd: Commands,
+    focus: Res<MenuFocus>,
+    items: Query<(Entity, &MenuFocusItem, &ChildOf)>,
+    mut areas: Query<(&mut ScrollPosition, &ComputedNode), With<ScrollArea>>,
 ) {
-    let Ok(interaction) = right_select_btn.single() else {
+    for (entity, item, child_of) in items.iter() {
+        if focus.idx != Some(item.0) {
+            cmd.entity(entity).remove::<Outline>();
+            continue;
+        }
+        cmd.entity(entity)
+            .insert(Outline::new(Val::Px(2.), Val::ZERO, FOCUS_OUTLINE));
+        let Ok((mut scroll, node)) = areas.get_mut(child_of.parent()) else {
+            continue;
+        };
+        let view_h = node.size().y * node.inverse_scale_factor();
+        let row_top = item.0 as f32 * (SETTINGS_ROW_H + SETTINGS_ROW_GAP);
+        if row_top < scroll.y {
+            scroll.y = row_top;
+        } else if row_top + SETTINGS_ROW_H > scroll.y + view_h {
+            scroll.y = row_top + SETTINGS_ROW_H - view_h;
+        }
+    }
+}
+
+// 帧末松开本帧由动作按下的按钮
+pub fn release_menu_press(mut focus: ResMut<MenuFocus>, mut buttons: Query<&mut Interaction>) {
+    // 先只读判断, 避免每帧都把 MenuFocus 标记为已修改
+    let Some(entity) = focus.pressed else {
         return;
     };
-    if let Interaction::Pressed = interaction {
-        lvs.cur_idx = (lvs.cur_idx + 1) % lvs.lvs.len();
-        let mut text = cur_lv_text.into_inner();
-        **text = Path::new(lvs.lvs[lvs.cur_idx].as_str())
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_owned();
+    focus.pressed = None;
+    if let Ok(mut interaction) = buttons.get_mut(entity) {
+        *interaction = Interaction::None;
+    }
+}
+
+// 鼠标滚轮滚动当前界面里的可滚动区域
+pub fn scroll_areas(
+    mut mouse_wheel: MessageReader<MouseWheel>,
+    mut areas: Query<&mut ScrollPosition, With<ScrollArea>>,
+) {
+    for event in mouse_wheel.read() {
+        let dy = match event.unit {
+            MouseScrollUnit::Line => event.y * SCROLL_LINE_H,
+            MouseScrollUnit::Pixel => event.y,
+        };
+        for mut area in areas.iter_mut() {
+            // 超出内容的部分由布局系统收回
+            area.y = (area.y - dy).max(0.0);
+        }
     }
 }
 
 pub fn return_main_ui(
     mut cmd: Commands,
     return_btn: Query<&Interaction, (Changed<Interaction>, With<ReturnMainMenuButton>)>,
-    map_item: Query<Entity, With<MapItem>>,
-    role: Single<Entity, With<RoleSpeed>>,
+    level_entities: Query<Entity, Or<(With<MapItem>, With<RoleSpeed>, With<LevelDecor>)>>,
     mut next_state: ResMut<NextState<GameState>>,
     mut lv_idx_entity_paires: ResMut<IdxEntityPair>,
+    mut practice: ResMut<Practice>,
 ) {
     let Ok(interaction) = return_btn.single() else {
         return;
     };
     if let Interaction::Pressed = interaction {
-        for entity in &map_item {
-            cmd.entity(entity).despawn();
-        }
-        cmd.entity(*role).despawn();
-        lv_idx_entity_paires.pairs.clear();
+        despawn_level(&mut cmd, &level_entities, &mut lv_idx_entity_paires);
+        *practice = Practice::default();
         next_state.set(GameState::Main);
     }
 }
 
-pub fn start_playing(state: Res<State<GameState>>, mut nxt_state: ResMut<NextState<GameState>>) {
-    match state.get() {
-        GameState::InitLevel => {
-            info!("game start");
-            nxt_state.set(GameState::Playing);
+// 进入通关界面时焦点在重玩上, 直接确认即重玩
+pub fn replay_button_action(
+    replay_btn: Query<&Interaction, (Changed<Interaction>, With<ReplayButton>)>,
+    mut restarts: MessageWriter<RestartLevel>,
+) {
+    if let Ok(Interaction::Pressed) = replay_btn.single() {
+        restarts.write(RestartLevel);
+    }
+}
+
+pub fn next_level_button_action(
+    mut cmd: Commands,
+    next_btn: Query<&Interaction, (Changed<Interaction>, With<NextLevelButton>)>,
+    level_entities: Query<Entity, Or<(With<MapItem>, With<RoleSpeed>, With<LevelDecor>)>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut lv_idx_entity_paires: ResMut<IdxEntityPair>,
+    mut lvs: ResMut<CurLevel>,
+    mut lvd: ResMut<LevelData>,
+    mut practice: ResMut<Practice>,
+) {
+    let Ok(interaction) = next_btn.single() else {
+        return;
+    };
+    if let Interaction::Pressed = interaction {
+        despawn_level(&mut cmd, &level_entities, &mut lv_idx_entity_paires);
+        practice.checkpoints.clear();
+        lvs.cur_idx = (lvs.cur_idx + 1) % lvs.lvs.len();
+        let lv = &lvs.lvs[lvs.cur_idx];
+        info!("next level {}", lv);
+        match LevelData::from_file(lv) {
+            Ok(level) => {
+                *lvd = level;
+                next_state.set(GameState::InitLevel);
+            }
+            // 下一关无效时回到主菜单
+            Err(e) => {
+                warn!("invalid level {}: {}", lv, e);
+                next_state.set(GameState::Main);
+            }
         }
-        _ => (),
+    }
+}
+
+// 主菜单和暂停菜单都能打开设置, 返回时回到打开前的界面
+pub fn open_settings_button_action(
+    settings_btn: Query<&Interaction, (Changed<Interaction>, With<SettingsButton>)>,
+    state: Res<State<GameState>>,
+    mut settings_origin: ResMut<SettingsOrigin>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Ok(interaction) = settings_btn.single() else {
+        return;
+    };
+    if let Interaction::Pressed = interaction {
+        settings_origin.0 = *state.get();
+        next_state.set(GameState::Settings);
+    }
+}
+
+pub fn resume_button_action(
+    resume_btn: Query<&Interaction, (Changed<Interaction>, With<ResumeButton>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Ok(interaction) = resume_btn.single() else {
+        return;
+    };
+    if let Interaction::Pressed = interaction {
+        info!("game continue");
+        next_state.set(GameState::Playing);
+    }
+}
+
+pub fn restart_button_action(
+    restart_btn: Query<&Interaction, (Changed<Interaction>, With<RestartButton>)>,
+    mut restarts: MessageWriter<RestartLevel>,
+) {
+    let Ok(interaction) = restart_btn.single() else {
+        return;
+    };
+    if let Interaction::Pressed = interaction {
+        restarts.write(RestartLevel);
+    }
+}
+
+// 切换练习模式后从头开始, 避免一次尝试里混着两种模式的成绩
+pub fn practice_toggle_action(
+    practice_btn: Query<&Interaction, (Changed<Interaction>, With<PracticeToggleButton>)>,
+    mut practice: ResMut<Practice>,
+    mut restarts: MessageWriter<RestartLevel>,
+) {
+    let Ok(interaction) = practice_btn.single() else {
+        return;
+    };
+    if let Interaction::Pressed = interaction {
+        practice.enabled = !practice.enabled;
+        info!("practice mode {}", practice.enabled);
+        restarts.write(RestartLevel);
+    }
+}
+
+fn setting_value(
+    locale: &Locale,
+    settings: &Settings,
+    rebinding: &Rebinding,
+    field: SettingField,
+) -> String {
+    if rebinding.0 == Some(field) {
+        return locale.get("press_to_bind");
+    }
+    let on_off = |b: bool| locale.get(if b { "on" } else { "off" });
+    match field {
+        SettingField::MusicVolume => format!("{:.0}%", settings.music_volume * 100.0),
+        SettingField::SfxVolume => format!("{:.0}%", settings.sfx_volume * 100.0),
+        SettingField::WindowMode => locale.get(settings.window_mode.name()),
+        SettingField::Hitboxes => on_off(settings.show_hitboxes),
+        SettingField::ProgressBar => on_off(settings.show_progress),
+        SettingField::Countdown => on_off(settings.countdown),
+        SettingField::RespawnDelay => format!("{:.2}s", settings.respawn_delay),
+        SettingField::LowEffects => on_off(settings.low_effects),
+        SettingField::SfxCategoryVolume(c) => {
+            format!("{:.0}%", settings.category_volume(c) * 100.0)
+        }
+        // 切换后字符串表随之重新加载, 语言名取该语言自己的写法
+        SettingField::Language => locale.get("language_name"),
+        SettingField::Binding(action) => settings
+            .bindings(action)
+            .into_iter()
+            .filter_map(|b| b.to_config())
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+pub fn settings_ui(
+    mut cmd: Commands,
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    locale: Res<Locale>,
+) {
+    let btn_bundle = (
+        Button,
+        Node {
+            min_width: Val::Px(65.),
+            height: Val::Px(32.),
+            padding: UiRect::horizontal(Val::Px(10.)),
+            // horizontally center child text
+            justify_content: JustifyContent::Center,
+            // vertically center child text
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(NORMAL_BUTTON),
+    );
+    let text_bundle = (
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.9, 0.9, 0.9)),
+    );
+
+    cmd.spawn(Node {
+        flex_direction: FlexDirection::Column,
+        width: Val::Percent(100.),
+        height: Val::Percent(100.),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        row_gap: Val::Px(4.),
+        ..default()
+    })
+    .with_children(|parent| {
+        let rows = [
+            ("setting_music_volume", SettingField::MusicVolume, true),
+            ("setting_sfx_volume", SettingField::SfxVolume, true),
+            ("setting_window_mode", SettingField::WindowMode, false),
+            ("setting_hitboxes", SettingField::Hitboxes, false),
+            ("setting_progress_bar", SettingField::ProgressBar, false),
+            ("setting_countdown", SettingField::Countdown, false),
+            ("setting_respawn_delay", SettingField::RespawnDelay, true),
+            ("setting_low_effects", SettingField::LowEffects, false),
+            ("setting_language", SettingField::Language, false),
+        ]
+        .into_iter()
+        .chain(
+            SfxCategory::ALL
+                .into_iter()
+                .map(|c| (c.name(), SettingField::SfxCategoryVolume(c), true)),
+        )
+        .chain(
+            Action::ALL
+                .into_iter()
+                .map(|action| (action.name(), SettingField::Binding(action), false)),
+        )
+        .collect::<Vec<_>>();
+        let row_count = rows.len();
+        // 设置项较多, 放在可滚动的区域里
+        parent
+            .spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(SETTINGS_ROW_GAP),
+                    max_height: Val::Percent(80.),
+                    overflow: Overflow::scroll_y(),
+                    ..default()
+                },
+                ScrollPosition::default(),
+                ScrollArea,
+            ))
+            .with_children(|parent| {
+                for (i, (label, field, stepped)) in rows.into_iter().enumerate() {
+                    let value = setting_value(&locale, &settings, &rebinding, field);
+                    parent
+                        .spawn((
+                            Node {
+                                width: Val::Px(520.),
+                                height: Val::Px(SETTINGS_ROW_H),
+                                justify_content: JustifyContent::SpaceBetween,
+                                align_items: AlignItems::Center,
+                                column_gap: Val::Px(10.),
+                                ..default()
+                            },
+                            MenuFocusItem(i),
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                text_bundle.clone(),
+                                Text::new(locale.get(label)),
+                                Localized(label),
+                            ));
+                            if stepped {
+                                parent
+                                    .spawn(Node {
+                                        align_items: AlignItems::Center,
+                                        column_gap: Val::Px(10.),
+                                        ..default()
+                                    })
+                                    .with_children(|parent| {
+                                        parent
+                                            .spawn((
+                                                btn_bundle.clone(),
+                                                SettingsItem(SettingAction::Decrease(field)),
+                                            ))
+                                            .with_children(|parent| {
+                                                parent.spawn((text_bundle.clone(), Text::new("-")));
+                                            });
+                                        parent.spawn((
+                                            text_bundle.clone(),
+                                            Text::new(value),
+                                            SettingsValue(field),
+                                        ));
+                                        parent
+                                            .spawn((
+                                                btn_bundle.clone(),
+                                                SettingsItem(SettingAction::Increase(field)),
+                                            ))
+                                            .with_children(|parent| {
+                                                parent.spawn((text_bundle.clone(), Text::new("+")));
+                                            });
+                                    });
+                            } else {
+                                parent
+                                    .spawn((
+                                        btn_bundle.clone(),
+                                        SettingsItem(SettingAction::Toggle(field)),
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            text_bundle.clone(),
+                                            Text::new(value),
+                                            SettingsValue(field),
+                                        ));
+                                    });
+                            }
+                        });
+                }
+            });
+        parent
+            .spawn(Node {
+                column_gap: Val::Px(10.),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn((
+                        btn_bundle.clone(),
+                        SettingsItem(SettingAction::ResetBindings),
+                        MenuFocusItem(row_count),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            text_bundle.clone(),
+                            Text::new(locale.get("reset_bindings")),
+                            Localized("reset_bindings"),
+                        ));
+                    });
+                parent
+                    .spawn((
+                        btn_bundle.clone(),
+                        SettingsItem(SettingAction::Back),
+                        MenuFocusItem(row_count + 1),
+                        MenuBackButton,
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            text_bundle.clone(),
+                            Text::new(locale.get("return")),
+                            Localized("return"),
+                        ));
+                    });
+            });
+    })
+    .insert(SettingsUIEntity);
+}
+
+pub fn leave_settings(mut cmd: Commands, settings_ui: Single<Entity, With<SettingsUIEntity>>) {
+    cmd.entity(*settings_ui).despawn();
+}
+
+pub fn settings_item_action(
+    items: Query<(&Interaction, &SettingsItem), Changed<Interaction>>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+    settings_origin: Res<SettingsOrigin>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, item) in items.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match item.0 {
+            SettingAction::Decrease(SettingField::MusicVolume) => {
+                settings.music_volume = (settings.music_volume - 0.1).max(0.0);
+            }
+            SettingAction::Increase(SettingField::MusicVolume) => {
+                settings.music_volume = (settings.music_volume + 0.1).min(1.0);
+            }
+            SettingAction::Decrease(SettingField::SfxVolume) => {
+                settings.sfx_volume = (settings.sfx_volume - 0.1).max(0.0);
+            }
+            SettingAction::Increase(SettingField::SfxVolume) => {
+                settings.sfx_volume = (settings.sfx_volume + 0.1).min(1.0);
+            }
+            SettingAction::Decrease(SettingField::SfxCategoryVolume(c)) => {
+                let volume = (settings.category_volume(c) - 0.1).max(0.0);
+                settings.sfx_categories.insert(c, volume);
+            }
+            SettingAction::Increase(SettingField::SfxCategoryVolume(c)) => {
+                let volume = (settings.category_volume(c) + 0.1).min(1.0);
+                settings.sfx_categories.insert(c, volume);
+            }
+            SettingAction::Decrease(SettingField::RespawnDelay) => {
+                settings.respawn_delay = (settings.respawn_delay - 0.25).max(0.0);
+            }
+            SettingAction::Increase(SettingField::RespawnDelay) => {
+                settings.respawn_delay = (settings.respawn_delay + 0.25).min(3.0);
+            }
+            SettingAction::Toggle(SettingField::WindowMode) => {
+                settings.window_mode = settings.window_mode.next();
+            }
+            SettingAction::Toggle(SettingField::Hitboxes) => {
+                settings.show_hitboxes = !settings.show_hitboxes;
+            }
+            SettingAction::Toggle(SettingField::ProgressBar) => {
+                settings.show_progress = !settings.show_progress;
+            }
+            SettingAction::Toggle(SettingField::Countdown) => {
+                settings.countdown = !settings.countdown;
+            }
+            SettingAction::Toggle(SettingField::LowEffects) => {
+                settings.low_effects = !settings.low_effects;
+            }
+            SettingAction::Toggle(SettingField::Language) => {
+                let langs = Locale::languages();
+                let cur = langs.iter().position(|l| *l == settings.language);
+                if let Some(lang) = cur.map_or(langs.first(), |i| langs.get((i + 1) % langs.len()))
+                {
+                    settings.language = lang.clone();
+                }
+            }
+            SettingAction::Toggle(field @ SettingField::Binding(_)) => {
+                rebinding.0 = Some(field);
+            }
+            SettingAction::ResetBindings => {
+                settings.bindings = default_bindings();
+            }
+            SettingAction::Back => {
+                rebinding.0 = None;
+                next_state.set(settings_origin.0);
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn capture_rebind(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+) {
+    // 刚点下改绑按钮的这一帧不捕获, 否则会把这次鼠标点击绑上去
+    if rebinding.is_changed() {
+        return;
+    }
+    let Some(SettingField::Binding(action)) = rebinding.0 else {
+        return;
+    };
+    // Esc 取消改绑, 保留原来的绑定
+    if keys.just_pressed(KeyCode::Escape) {
+        info!("cancel rebind {:?}", action);
+        rebinding.0 = None;
+        return;
+    }
+    let Some(binding) = first_just_pressed(&keys, &mouse, &gamepads) else {
+        return;
+    };
+    info!("rebind {:?} to {:?}", action, binding);
+    settings.set_binding(action, binding);
+    rebinding.0 = None;
+}
+
+pub fn settings_labels(
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    locale: Res<Locale>,
+    mut labels: Query<(&mut Text, &SettingsValue)>,
+) {
+    for (mut text, value) in labels.iter_mut() {
+        text.0 = setting_value(&locale, &settings, &rebinding, value.0);
+    }
+}
+
+fn customize_value(locale: &Locale, profile: &Profile, field: CustomizeField) -> String {
+    match field {
+        CustomizeField::Icon => profile.icon.clone(),
+        CustomizeField::Primary => profile.primary.clone(),
+        CustomizeField::Secondary => profile.secondary.clone(),
+        CustomizeField::Trail => locale.get(profile.trail.name()),
+    }
+}
+
+// 在列表里循环取当前项的前一个或后一个, 当前项不在列表里时取第一个
+fn cycle<T: PartialEq + Clone>(items: &[T], cur: &T, forward: bool) -> Option<T> {
+    let len = items.len();
+    let idx = match items.iter().position(|item| item == cur) {
+        Some(i) if forward => (i + 1) % len,
+        Some(i) => (i + len - 1) % len,
+        None => 0,
+    };
+    items.get(idx).cloned()
+}
+
+pub fn open_customize_button_action(
+    customize_btn: Query<&Interaction, (Changed<Interaction>, With<CustomizeButton>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Ok(interaction) = customize_btn.single() else {
+        return;
+    };
+    if let Interaction::Pressed = interaction {
+        next_state.set(GameState::Customize);
+    }
+}
+
+pub fn customize_ui(
+    mut cmd: Commands,
+    save: Res<SaveData>,
+    locale: Res<Locale>,
+    asset_server: Res<AssetServer>,
+) {
+    let btn_bundle = (
+        Button,
+        Node {
+            min_width: Val::Px(40.),
+            height: Val::Px(32.),
+            padding: UiRect::horizontal(Val::Px(10.)),
+            // horizontally center child text
+            justify_content: JustifyContent::Center,
+            // vertically center child text
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(NORMAL_BUTTON),
+    );
+    let text_bundle = (
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.9, 0.9, 0.9)),
+    );
+    let profile = &save.profile;
+    let (primary, secondary) = icon_images(&asset_server, &profile.icon);
+
+    cmd.spawn(Node {
+        flex_direction: FlexDirection::Column,
+        width: Val::Percent(100.),
+        height: Val::Percent(100.),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        row_gap: Val::Px(8.),
+        ..default()
+    })
+    .with_children(|parent| {
+        // 两层图标叠在一起预览
+        parent
+            .spawn(Node {
+                width: Val::Px(128.),
+                height: Val::Px(128.),
+                margin: UiRect::bottom(Val::Px(12.)),
+                ..default()
+            })
+            .with_children(|parent| {
+                for (image, color, secondary) in [
+                    (primary, &profile.primary, false),
+                    (secondary, &profile.secondary, true),
+                ] {
+                    parent.spawn((
+                        ImageNode::new(image).with_color(parse_color(Some(color.as_str()))),
+                        Node {
+                            position_type: PositionType::Absolute,
+                            width: Val::Percent(100.),
+                            height: Val::Percent(100.),
+                            ..default()
+                        },
+                        IconPreview(secondary),
+                    ));
+                }
+            });
+        let rows = [
+            ("customize_icon", CustomizeField::Icon),
+            ("customize_primary", CustomizeField::Primary),
+            ("customize_secondary", CustomizeField::Secondary),
+            ("customize_trail", CustomizeField::Trail),
+        ];
+        let back_idx = rows.len();
+        for (i, (label, field)) in rows.into_iter().enumerate() {
+            parent
+                .spawn((
+                    Node {
+                        width: Val::Px(420.),
+                        justify_content: JustifyContent::SpaceBetween,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    MenuFocusItem(i),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        text_bundle.clone(),
+                        Text::new(locale.get(label)),
+                        Localized(label),
+                    ));
+                    parent
+                        .spawn(Node {
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(10.),
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn((
+                                    btn_bundle.clone(),
+                                    CustomizeItem(CustomizeAction::Prev(field)),
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((text_bundle.clone(), Text::new("<")));
+                                });
+                            parent.spawn((
+                                text_bundle.clone(),
+                                Text::new(customize_value(&locale, profile, field)),
+                                CustomizeValue(field),
+                            ));
+                            parent
+                                .spawn((
+                                    btn_bundle.clone(),
+                                    CustomizeItem(CustomizeAction::Next(field)),
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((text_bundle.clone(), Text::new(">")));
+                                });
+                        });
+                });
+        }
+        parent
+            .spawn((
+                btn_bundle.clone(),
+                CustomizeItem(CustomizeAction::Back),
+                MenuFocusItem(back_idx),
+                MenuBackButton,
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    text_bundle.clone(),
+                    Text::new(locale.get("return")),
+                    Localized("return"),
+                ));
+            });
+    })
+    .insert(CustomizeUIEntity);
+}
+
+pub fn leave_customize(mut cmd: Commands, customize_ui: Single<Entity, With<CustomizeUIEntity>>) {
+    cmd.entity(*customize_ui).despawn();
+}
+
+// 修改的外观存进存档, 下次进入关卡时生效
+pub fn customize_item_action(
+    items: Query<(&Interaction, &CustomizeItem), Changed<Interaction>>,
+    mut save: ResMut<SaveData>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, item) in items.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let (field, forward) = match item.0 {
+            CustomizeAction::Prev(field) => (field, false),
+            CustomizeAction::Next(field) => (field, true),
+            CustomizeAction::Back => {
+                next_state.set(GameState::Main);
+                continue;
+            }
+        };
+        let colors = PLAYER_COLORS.map(String::from);
+        let profile = &mut save.profile;
+        match field {
+            CustomizeField::Icon => {
+                if let Some(icon) = cycle(&icon_names(), &profile.icon, forward) {
+                    profile.icon = icon;
+                }
+            }
+            CustomizeField::Primary => {
+                if let Some(color) = cycle(&colors, &profile.primary, forward) {
+                    profile.primary = color;
+                }
+            }
+            CustomizeField::Secondary => {
+                if let Some(color) = cycle(&colors, &profile.secondary, forward) {
+                    profile.secondary = color;
+                }
+            }
+            CustomizeField::Trail => {
+                if let Some(trail) = cycle(&TrailKind::ALL, &profile.trail, forward) {
+                    profile.trail = trail;
+                }
+            }
+        }
+    }
+}
+
+pub fn customize_labels(
+    save: Res<SaveData>,
+    locale: Res<Locale>,
+    asset_server: Res<AssetServer>,
+    mut labels: Query<(&mut Text, &CustomizeValue)>,
+    mut previews: Query<(&mut ImageNode, &IconPreview)>,
+) {
+    let profile = &save.profile;
+    for (mut text, value) in labels.iter_mut() {
+        text.0 = customize_value(&locale, profile, value.0);
+    }
+    let (primary, secondary) = icon_images(&asset_server, &profile.icon);
+    for (mut image, preview) in previews.iter_mut() {
+        let (handle, color) = if preview.0 {
+            (secondary.clone(), &profile.secondary)
+        } else {
+            (primary.clone(), &profile.primary)
+        };
+        image.image = handle;
+        image.color = parse_color(Some(color.as_str()));
+    }
+}
+
+fn countdown_text(countdown: &Countdown) -> String {
+    format!(
+        "{}",
+        countdown.timer.remaining_secs().ceil().max(1.0) as u32
+    )
+}
+
+// 进入关卡后倒计时自动开始; 死亡重开或设置里关掉倒计时时直接开始
+pub fn countdown_ui(mut cmd: Commands, settings: Res<Settings>, stats: Res<SessionStats>) {
+    let secs = if stats.respawn || !settings.countdown {
+        0.0
+    } else {
+        COUNTDOWN_SECS
+    };
+    let countdown = Countdown {
+        timer: Timer::from_seconds(secs, TimerMode::Once),
+    };
+    if secs > 0.0 {
+        cmd.spawn(Node {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(countdown_text(&countdown)),
+                TextFont {
+                    font_size: 96.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                CountdownLabel,
+            ));
+        })
+        .insert(CountdownUIEntity);
+    }
+    cmd.insert_resource(countdown);
+}
+
+pub fn countdown_update(
+    time: Res<Time>,
+    mut countdown: ResMut<Countdown>,
+    mut stats: ResMut<SessionStats>,
+    mut labels: Query<&mut Text, With<CountdownLabel>>,
+    mut nxt_state: ResMut<NextState<GameState>>,
+) {
+    countdown.timer.tick(time.delta());
+    for mut text in labels.iter_mut() {
+        text.0 = countdown_text(&countdown);
+    }
+    if countdown.timer.is_finished() {
+        info!("game start");
+        stats.respawn = false;
+        nxt_state.set(GameState::Playing);
+    }
+}
+
+pub fn leave_countdown(mut cmd: Commands, countdown_ui: Query<Entity, With<CountdownUIEntity>>) {
+    for entity in countdown_ui.iter() {
+        cmd.entity(entity).despawn();
+    }
+    cmd.remove_resource::<Countdown>();
+}
+
+// 界面按窗口高度相对 VIRTUAL_HEIGHT 的比例缩放, 和镜头保持一致
+pub fn scale_ui(window: Single<&Window, With<PrimaryWindow>>, mut ui_scale: ResMut<UiScale>) {
+    let scale = window.height() / VIRTUAL_HEIGHT;
+    if scale > 0.0 && ui_scale.0 != scale {
+        ui_scale.0 = scale;
     }
 }